@@ -0,0 +1,184 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{
+    include::expand,
+    report::{analyze_source, AnalysisOptions, Row, Totals},
+};
+
+const SOURCE_EXTENSIONS: [&str; 3] = ["asm", "a51", "s"];
+
+pub struct FileSummary {
+    pub path: PathBuf,
+    pub bytes: usize,
+    pub cycles: usize,
+    pub errors: usize,
+}
+
+impl FileSummary {
+    pub fn of(path: &Path, rows: &[Row]) -> Self {
+        let totals = Totals::of(rows);
+        FileSummary {
+            path: path.to_path_buf(),
+            bytes: totals.bytes,
+            cycles: totals.cycles,
+            errors: rows.iter().filter(|row| row.has_error()).count(),
+        }
+    }
+}
+
+fn is_source(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn collect_dir(dir: &Path, res: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_dir(&path, res)?;
+        } else if is_source(&path) {
+            res.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Expands the given inputs, recursing into directories for `.asm`, `.a51` and `.s` files.
+pub fn collect_inputs(inputs: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut res = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            collect_dir(input, &mut res)?;
+        } else {
+            res.push(input.clone());
+        }
+    }
+    Ok(res)
+}
+
+/// Reads, expands the includes of, and analyzes every file on a pool of worker threads,
+/// keeping the input order.
+pub fn analyze_all<F>(
+    files: &[PathBuf],
+    options: &AnalysisOptions,
+    read: F,
+) -> Vec<io::Result<Vec<Row>>>
+where
+    F: Fn(&Path) -> io::Result<String> + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1)
+        .min(files.len().max(1));
+    let next = AtomicUsize::new(0);
+    let mut res = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(file) = files.get(index) else {
+                            break done;
+                        };
+                        let rows = read(file).map(|contents| {
+                            let source = expand(file, &contents, &options.include_paths, &read);
+                            analyze_source(&source, options)
+                        });
+                        done.push((index, rows));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    res.sort_by_key(|(index, _)| *index);
+    res.into_iter().map(|(_, rows)| rows).collect()
+}
+
+/// Names of the per-file reports written into an output directory, one per input, with
+/// `extension` appended or, when `None`, as the input's own file name.
+///
+/// A name joins the input's path components and keeps its extension, so `subs/main.asm`
+/// becomes `subs_main.asm.csv`. Names that would still collide, such as those of `x.asm`
+/// and `../x.asm`, get a `-2`, `-3`... suffix, compared ignoring case for filesystems that
+/// do. `summary` is kept free for the combined summary.
+pub fn report_names(paths: &[PathBuf], extension: Option<&str>) -> Vec<PathBuf> {
+    let mut used = HashSet::from([String::from("summary")]);
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path
+                .with_extension("")
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(part) => part.to_str(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("_");
+            let source = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| format!(".{}", ext))
+                .unwrap_or_default();
+            let name = (1..)
+                .map(|n| match n {
+                    1 => format!("{}{}", stem, source),
+                    n => format!("{}-{}{}", stem, n, source),
+                })
+                .find(|name| used.insert(name.to_ascii_lowercase()))
+                .unwrap();
+            match extension {
+                Some(extension) => PathBuf::from(format!("{}.{}", name, extension)),
+                None => PathBuf::from(name),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let paths = [
+            "./subs/alice/main.asm",
+            "main.asm",
+            "main.s",
+            "../main.asm",
+            "a/b_c.asm",
+            "a_b/c.asm",
+            "summary",
+        ]
+        .map(PathBuf::from);
+        let names = report_names(&paths, Some("csv"));
+        let expected = [
+            "subs_alice_main.asm.csv",
+            "main.asm.csv",
+            "main.s.csv",
+            "main-2.asm.csv",
+            "a_b_c.asm.csv",
+            "a_b_c-2.asm.csv",
+            "summary-2.csv",
+        ];
+        assert_eq!(expected.map(PathBuf::from).to_vec(), names);
+        assert_eq!(PathBuf::from("main.asm"), report_names(&paths[1..2], None)[0]);
+        assert!(is_source(Path::new("LAB2.A51")));
+        assert!(!is_source(Path::new("notes.txt")));
+    }
+}
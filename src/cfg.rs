@@ -0,0 +1,233 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+use crate::{report::Row, segment::Space};
+
+/// How an instruction passes control on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Continues with the next instruction.
+    Next,
+    /// Always continues at its target, like `SJMP`.
+    Jump,
+    /// Continues at its target or with the next instruction, like `DJNZ`.
+    Branch,
+    /// Runs its target and then continues with the next instruction.
+    Call,
+    Return,
+    /// Continues at an address computed at run time, `JMP @A+DPTR`.
+    Indirect,
+}
+
+pub fn flow(mnemonic: &str) -> Flow {
+    match mnemonic {
+        "AJMP" | "LJMP" | "SJMP" => Flow::Jump,
+        "JZ" | "JNZ" | "JC" | "JNC" | "JB" | "JNB" | "JBC" | "CJNE" | "DJNZ" => Flow::Branch,
+        "ACALL" | "LCALL" => Flow::Call,
+        "RET" | "RETI" => Flow::Return,
+        "JMP" => Flow::Indirect,
+        _ => Flow::Next,
+    }
+}
+
+/// A straight run of instructions that is only entered at its first one.
+pub struct Block {
+    pub start: usize,
+    /// Address after the last instruction.
+    pub end: usize,
+    pub label: Option<String>,
+    /// Indices of the rows of the block's instructions.
+    pub rows: Vec<usize>,
+    pub bytes: usize,
+    pub cycles: usize,
+    /// How the last instruction passes control on.
+    pub exit: Flow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    FallThrough,
+    Taken,
+    Call,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// The control-flow graph of the code space, with blocks in source order.
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+}
+
+/// The rows the graph is built from: valid, assembled instructions in the code space.
+fn instructions(rows: &[Row]) -> impl Iterator<Item = (usize, &Row, &str)> {
+    rows.iter().enumerate().filter_map(|(index, row)| {
+        let mnemonic = row.mnemonic.as_deref()?;
+        (row.space == Space::Code && !row.inactive && !row.has_error())
+            .then_some((index, row, mnemonic))
+    })
+}
+
+impl Cfg {
+    pub fn of(rows: &[Row]) -> Self {
+        let leaders = instructions(rows)
+            .filter(|(_, _, mnemonic)| matches!(flow(mnemonic), Flow::Jump | Flow::Branch | Flow::Call))
+            .filter_map(|(_, row, _)| row.target)
+            .collect::<HashSet<_>>();
+        let mut labels = HashMap::new();
+        for row in rows.iter().filter(|row| row.space == Space::Code && !row.inactive) {
+            if let Some(label) = &row.label {
+                labels.entry(row.address).or_insert(label.clone());
+            }
+        }
+
+        let mut blocks: Vec<Block> = vec![];
+        for (index, row, mnemonic) in instructions(rows) {
+            let continues = blocks
+                .last()
+                .is_some_and(|block| block.exit == Flow::Next && block.end == row.address);
+            if !continues || leaders.contains(&row.address) {
+                blocks.push(Block {
+                    start: row.address,
+                    end: row.address,
+                    label: labels.get(&row.address).cloned(),
+                    rows: vec![],
+                    bytes: 0,
+                    cycles: 0,
+                    exit: Flow::Next,
+                });
+            }
+            let block = blocks.last_mut().unwrap();
+            let bytes = row.bytes.clone().unwrap_or(0);
+            block.rows.push(index);
+            block.end = row.address + bytes;
+            block.bytes += bytes;
+            block.cycles += row.cycles.clone().unwrap_or(0);
+            block.exit = flow(mnemonic);
+        }
+
+        let starts = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.start, index))
+            .collect::<HashMap<_, _>>();
+        let mut edges = vec![];
+        for (from, block) in blocks.iter().enumerate() {
+            let target = block
+                .rows
+                .last()
+                .and_then(|&index| rows[index].target)
+                .and_then(|target| starts.get(&target));
+            let next = starts.get(&block.end);
+            let mut edge = |to: Option<&usize>, kind| {
+                if let Some(&to) = to {
+                    edges.push(Edge { from, to, kind });
+                }
+            };
+            match block.exit {
+                Flow::Next => edge(next, EdgeKind::FallThrough),
+                Flow::Jump => edge(target, EdgeKind::Taken),
+                Flow::Branch => {
+                    edge(next, EdgeKind::FallThrough);
+                    edge(target, EdgeKind::Taken);
+                }
+                Flow::Call => {
+                    edge(target, EdgeKind::Call);
+                    edge(next, EdgeKind::FallThrough);
+                }
+                Flow::Return | Flow::Indirect => {}
+            }
+        }
+        Cfg { blocks, edges }
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes the graph in Graphviz DOT, each block listing its instructions under its
+/// address range, bytes and cycles.
+pub fn write_dot<W: Write>(cfg: &Cfg, rows: &[Row], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "digraph cfg {{")?;
+    writeln!(writer, "    node [shape=box, fontname=\"monospace\"];")?;
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        let mut label = match &block.label {
+            Some(name) => format!("{}\\l", dot_escape(name)),
+            None => String::new(),
+        };
+        label.push_str(&format!(
+            "{:04X}-{:04X}: {} bytes, {} cycles\\l\\l",
+            block.start,
+            block.end.saturating_sub(1),
+            block.bytes,
+            block.cycles
+        ));
+        for &row in &block.rows {
+            label.push_str(&format!("{}\\l", dot_escape(&rows[row].statement)));
+        }
+        writeln!(writer, "    b{} [label=\"{}\"];", index, label)?;
+    }
+    for edge in &cfg.edges {
+        let attributes = match edge.kind {
+            EdgeKind::FallThrough => "",
+            EdgeKind::Taken => " [label=\"taken\"]",
+            EdgeKind::Call => " [label=\"call\", style=dashed]",
+        };
+        writeln!(writer, "    b{} -> b{}{};", edge.from, edge.to, attributes)?;
+    }
+    writeln!(writer, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn blocks_and_edges() {
+        let source = "MAIN: MOV R7, #10\nLOOP: ACALL WORK\nDJNZ R7, LOOP\nHALT: SJMP HALT\nWORK: CPL P1.0\nRET";
+        let rows = analyze(source, &AnalysisOptions::default());
+        let cfg = Cfg::of(&rows);
+        let blocks = cfg
+            .blocks
+            .iter()
+            .map(|block| (block.label.as_deref(), block.start, block.bytes, block.cycles))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Some("MAIN"), 0, 2, 1),
+                (Some("LOOP"), 2, 2, 2),
+                (None, 4, 2, 2),
+                (Some("HALT"), 6, 2, 2),
+                (Some("WORK"), 8, 3, 3),
+            ],
+            blocks
+        );
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            vec![
+                edge(0, 1, EdgeKind::FallThrough),
+                edge(1, 4, EdgeKind::Call),
+                edge(1, 2, EdgeKind::FallThrough),
+                edge(2, 3, EdgeKind::FallThrough),
+                edge(2, 1, EdgeKind::Taken),
+                edge(3, 3, EdgeKind::Taken),
+            ],
+            cfg.edges
+        );
+
+        let mut dot = vec![];
+        write_dot(&cfg, &rows, &mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("b4 [label=\"WORK\\l0008-000A: 3 bytes, 3 cycles\\l\\lCPL P1.0\\lRET\\l\"];"));
+        assert!(dot.contains("b1 -> b4 [label=\"call\", style=dashed];"));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{encoding::resolve, literal::parse_number, symbols::SymbolTable};
+
+/// A conditional assembly directive, in A51 (`IF`), control (`$IF`) or SDCC (`.if`) spelling.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Conditional<'a> {
+    If(&'a str),
+    IfDef(&'a str),
+    IfNDef(&'a str),
+    ElseIf(&'a str),
+    Else,
+    EndIf,
+}
+
+pub fn conditional(statement: &str) -> Option<Conditional<'_>> {
+    let statement = statement.trim_start_matches(['.', '$']);
+    let (directive, args) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    let args = args.trim();
+    match directive.to_ascii_uppercase().as_str() {
+        "IF" => Some(Conditional::If(args)),
+        "IFDEF" => Some(Conditional::IfDef(args)),
+        "IFNDEF" => Some(Conditional::IfNDef(args)),
+        "ELSEIF" => Some(Conditional::ElseIf(args)),
+        "ELSE" => Some(Conditional::Else),
+        "ENDIF" => Some(Conditional::EndIf),
+        _ => None,
+    }
+}
+
+fn tokens(expr: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut chars = expr.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = c.to_string();
+        if c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '?' | '\'') {
+            while let Some(&next) = chars.peek() {
+                if !(next.is_ascii_alphanumeric() || matches!(next, '_' | '$' | '?' | '\'')) {
+                    break;
+                }
+                token.push(next);
+                chars.next();
+            }
+        } else if let Some(&next) = chars.peek() {
+            if matches!((c, next), ('=' | '!' | '<' | '>', '=') | ('<', '>') | ('&', '&') | ('|', '|')) {
+                token.push(next);
+                chars.next();
+            }
+        }
+        res.push(token);
+    }
+    res
+}
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    position: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<String> {
+        self.tokens.get(self.position).map(|token| token.to_ascii_uppercase())
+    }
+
+    fn eat(&mut self, expected: &[&str]) -> Option<String> {
+        let token = self.peek().filter(|token| expected.contains(&token.as_str()))?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn or(&mut self) -> Result<i64, String> {
+        let mut res = self.and()?;
+        while self.eat(&["OR", "||"]).is_some() {
+            let rhs = self.and()?;
+            res = (res != 0 || rhs != 0) as i64;
+        }
+        Ok(res)
+    }
+
+    fn and(&mut self) -> Result<i64, String> {
+        let mut res = self.not()?;
+        while self.eat(&["AND", "&&"]).is_some() {
+            let rhs = self.not()?;
+            res = (res != 0 && rhs != 0) as i64;
+        }
+        Ok(res)
+    }
+
+    fn not(&mut self) -> Result<i64, String> {
+        if self.eat(&["NOT", "!"]).is_some() {
+            return Ok((self.not()? == 0) as i64);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<i64, String> {
+        let lhs = self.sum()?;
+        let operators = ["=", "==", "EQ", "<>", "!=", "NE", "<", "LT", "<=", "LE", ">", "GT", ">=", "GE"];
+        let Some(operator) = self.eat(&operators) else {
+            return Ok(lhs);
+        };
+        let rhs = self.sum()?;
+        Ok(match operator.as_str() {
+            "=" | "==" | "EQ" => lhs == rhs,
+            "<>" | "!=" | "NE" => lhs != rhs,
+            "<" | "LT" => lhs < rhs,
+            "<=" | "LE" => lhs <= rhs,
+            ">" | "GT" => lhs > rhs,
+            _ => lhs >= rhs,
+        } as i64)
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut res = self.atom()?;
+        while let Some(operator) = self.eat(&["+", "-"]) {
+            let rhs = self.atom()?;
+            res = if operator == "+" { res + rhs } else { res - rhs };
+        }
+        Ok(res)
+    }
+
+    fn atom(&mut self) -> Result<i64, String> {
+        if self.eat(&["("]).is_some() {
+            let res = self.or()?;
+            return self.eat(&[")"]).map(|_| res).ok_or_else(|| String::from("missing `)`"));
+        }
+        if self.eat(&["-"]).is_some() {
+            return Ok(-self.atom()?);
+        }
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| String::from("incomplete condition"))?;
+        self.position += 1;
+        resolve(&token, self.symbols).ok_or_else(|| format!("undefined symbol `{}`", token))
+    }
+}
+
+/// Evaluates the condition of an `IF`, where any non-zero value is true.
+pub fn evaluate(expr: &str, symbols: &SymbolTable) -> Result<i64, String> {
+    let mut parser = Parser {
+        tokens: tokens(expr),
+        position: 0,
+        symbols,
+    };
+    let res = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected `{}` in condition", token)),
+        None => Ok(res),
+    }
+}
+
+/// Parses a `-D` definition, `NAME=VALUE` or just `NAME` for 1.
+pub fn parse_define(text: &str) -> Result<(String, i64), String> {
+    let (name, value) = text.split_once('=').unwrap_or((text, "1"));
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid symbol name `{}`", name));
+    }
+    let value = parse_number(value.trim()).ok_or_else(|| format!("invalid value `{}`", value))?;
+    Ok((name.to_string(), value))
+}
+
+struct Frame {
+    /// Whether the enclosing block is assembled.
+    outer: bool,
+    /// Whether a branch of this block was already taken.
+    taken: bool,
+    active: bool,
+    seen_else: bool,
+}
+
+/// The nesting of open conditional blocks.
+#[derive(Default)]
+pub struct Conditions {
+    frames: Vec<Frame>,
+}
+
+impl Conditions {
+    /// Whether lines at the current position are assembled.
+    pub fn active(&self) -> bool {
+        self.frames.last().is_none_or(|frame| frame.active)
+    }
+
+    /// Whether the block around the current one is assembled, which decides how the
+    /// directive lines of the current block are shown.
+    pub fn outer(&self) -> bool {
+        self.frames.last().is_none_or(|frame| frame.outer)
+    }
+
+    /// Applies a directive. A condition that cannot be evaluated counts as false, so the
+    /// blocks still nest correctly after the error.
+    pub fn apply(&mut self, directive: Conditional, symbols: &SymbolTable) -> Result<(), String> {
+        let outer = self.active();
+        let test = |expr: &str| evaluate(expr, symbols).map(|value| value != 0);
+        match directive {
+            Conditional::If(expr) => {
+                let res = if outer { test(expr) } else { Ok(false) };
+                self.push(outer, res.clone().unwrap_or(false));
+                res?;
+            }
+            Conditional::IfDef(name) => self.push(outer, outer && symbols.contains(name)),
+            Conditional::IfNDef(name) => self.push(outer, outer && !symbols.contains(name)),
+            Conditional::ElseIf(expr) => {
+                let frame = self.frames.last_mut().ok_or("ELSEIF without IF")?;
+                if frame.seen_else {
+                    return Err(String::from("ELSEIF after ELSE"));
+                }
+                let res = match frame.outer && !frame.taken {
+                    true => test(expr),
+                    false => Ok(false),
+                };
+                frame.active = res.clone().unwrap_or(false);
+                frame.taken |= frame.active;
+                res?;
+            }
+            Conditional::Else => {
+                let frame = self.frames.last_mut().ok_or("ELSE without IF")?;
+                if frame.seen_else {
+                    return Err(String::from("second ELSE in the same IF"));
+                }
+                frame.seen_else = true;
+                frame.active = frame.outer && !frame.taken;
+                frame.taken = true;
+            }
+            Conditional::EndIf => {
+                self.frames.pop().ok_or("ENDIF without IF")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, outer: bool, active: bool) {
+        self.frames.push(Frame {
+            outer,
+            taken: active,
+            active,
+            seen_else: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions() {
+        let symbols = SymbolTable::from([("DEBUG", 1), ("LEVEL", 3)]);
+        assert_eq!(Ok(1), evaluate("DEBUG AND (LEVEL >= 2)", &symbols));
+        assert_eq!(Ok(0), evaluate("NOT DEBUG OR LEVEL = 0FH", &symbols));
+        assert_eq!(Ok(1), evaluate("LEVEL - 3 == 0", &symbols));
+        assert!(evaluate("RELEASE", &symbols).is_err());
+        assert_eq!(Some(Conditional::If("DEBUG")), conditional(".if DEBUG"));
+
+        let mut conditions = Conditions::default();
+        conditions.apply(Conditional::If("DEBUG"), &symbols).unwrap();
+        conditions.apply(Conditional::IfNDef("LEVEL"), &symbols).unwrap();
+        assert!(!conditions.active());
+        conditions.apply(Conditional::Else, &symbols).unwrap();
+        assert!(conditions.active());
+        conditions.apply(Conditional::EndIf, &symbols).unwrap();
+        conditions.apply(Conditional::Else, &symbols).unwrap();
+        assert!(!conditions.active());
+        conditions.apply(Conditional::EndIf, &symbols).unwrap();
+        assert!(conditions.apply(Conditional::EndIf, &symbols).is_err());
+
+        assert_eq!(Ok((String::from("DEBUG"), 1)), parse_define("DEBUG"));
+        assert_eq!(Ok((String::from("BAUD"), 0xFD)), parse_define("BAUD=0FDH"));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    dialect::Dialect,
+    get_cycle,
+    literal::LiteralDialect,
+    matching::make_matcher,
+    parser::{get_all_inst_variants, get_dialect_regex, get_dialect_skip_list},
+};
+
+/// Registers counting the nested loops, from the outermost.
+const COUNTERS: [&str; 3] = ["R7", "R6", "R5"];
+
+/// The most `NOP`s added to make up cycles the loop counts cannot.
+const MAX_PADDING: usize = 8;
+
+/// Parses a delay such as `10ms`, `500us` or `1.5s` into seconds.
+pub fn parse_duration(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let lower = text.to_ascii_lowercase();
+    let (number, scale) = if let Some(number) = lower.strip_suffix("ms") {
+        (number, 1e-3)
+    } else if let Some(number) = lower.strip_suffix("us").or_else(|| lower.strip_suffix("µs")) {
+        (number, 1e-6)
+    } else if let Some(number) = lower.strip_suffix("ns") {
+        (number, 1e-9)
+    } else {
+        (lower.strip_suffix('s').unwrap_or(&lower), 1.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(value * scale),
+        _ => Err(format!("invalid delay `{}`", text)),
+    }
+}
+
+/// Machine cycles of the instructions a delay routine is made of, from the same cycle
+/// model the table uses.
+struct Costs {
+    call: usize,
+    mov: usize,
+    djnz: usize,
+    nop: usize,
+    ret: usize,
+}
+
+impl Costs {
+    fn new() -> Self {
+        let all_inst = get_all_inst_variants();
+        let regex_map = get_dialect_regex(Dialect::default(), LiteralDialect::Any);
+        let skip_list = get_dialect_skip_list(Dialect::default());
+        let matcher = make_matcher();
+        let cost = |statement: &str| {
+            get_cycle(statement, &matcher, &all_inst, &regex_map, &skip_list)
+                .expect("delay routines only use valid instructions")
+        };
+        Costs {
+            call: cost("LCALL DELAY"),
+            mov: cost("MOV R7, #1"),
+            djnz: cost("DJNZ R7, DELAY"),
+            nop: cost("NOP"),
+            ret: cost("RET"),
+        }
+    }
+
+    /// Cycles of nested loops running `counts` times each, from the outermost.
+    fn loops(&self, counts: &[usize]) -> usize {
+        match counts {
+            [] => 0,
+            [count, inner @ ..] => self.mov + count * (self.loops(inner) + self.djnz),
+        }
+    }
+}
+
+/// A delay subroutine of nested `DJNZ` loops.
+pub struct Delay {
+    pub name: String,
+    /// Iterations of each loop, from the outermost.
+    pub counts: Vec<usize>,
+    /// `NOP`s before the `RET`.
+    pub padding: usize,
+    /// Machine cycles asked for and taken, counting the `LCALL` that calls the routine.
+    pub target: usize,
+    pub cycles: usize,
+}
+
+impl Delay {
+    /// The fewest loops whose cycles, padded with a few `NOP`s, come closest to `target`
+    /// machine cycles.
+    pub fn of(name: &str, target: usize) -> Result<Self, String> {
+        let costs = Costs::new();
+        let budget = target
+            .checked_sub(costs.call + costs.ret)
+            .ok_or_else(|| format!("{} cycles are fewer than calling and returning take", target))?;
+        for depth in 0..=COUNTERS.len() {
+            let mut best: Option<(Vec<usize>, usize)> = None;
+            let mut counts = vec![1; depth];
+            loop {
+                if let Some(last) = depth.checked_sub(1) {
+                    // The innermost count is the largest that does not overshoot, 0 if none.
+                    let (mut low, mut high) = (0usize, 256);
+                    while low < high {
+                        counts[last] = (low + high).div_ceil(2);
+                        match costs.loops(&counts) <= budget {
+                            true => low = counts[last],
+                            false => high = counts[last] - 1,
+                        }
+                    }
+                    counts[last] = low;
+                }
+                if counts.iter().all(|&count| count > 0) {
+                    let padding = budget - costs.loops(&counts);
+                    if best.as_ref().is_none_or(|(_, best)| padding < *best) {
+                        best = Some((counts.clone(), padding));
+                    }
+                }
+                // Next combination of the outer counts.
+                let Some(outer) = (0..depth.saturating_sub(1)).rev().find(|&index| counts[index] < 256) else {
+                    break;
+                };
+                counts[outer] += 1;
+                counts[outer + 1..].fill(1);
+            }
+            if let Some((counts, padding)) = best.filter(|(_, padding)| *padding <= MAX_PADDING) {
+                return Ok(Delay {
+                    name: name.to_string(),
+                    cycles: costs.call + costs.loops(&counts) + padding * costs.nop + costs.ret,
+                    counts,
+                    padding,
+                    target,
+                });
+            }
+        }
+        Err(format!("{} cycles need more than {} nested loops", target, COUNTERS.len()))
+    }
+
+    /// The routine's source, one instruction per line with the loop labels in front.
+    pub fn source(&self) -> String {
+        let label = |index: usize| format!("{}_{}", self.name, index + 1);
+        let depth = self.counts.len();
+        let mut lines = vec![];
+        for (index, count) in self.counts.iter().enumerate() {
+            lines.push(format!("MOV {}, #{}", COUNTERS[index], count % 256));
+        }
+        for index in (0..depth).rev() {
+            lines.push(format!("DJNZ {}, {}", COUNTERS[index], label(index)));
+        }
+        lines.extend(vec![String::from("NOP"); self.padding]);
+        lines.push(String::from("RET"));
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| match index {
+                0 => format!("{}: {}", self.name, line),
+                _ if (1..=depth).contains(&index) => format!("{}: {}", label(index - 1), line),
+                _ => format!("    {}", line),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, cycle_time, parse_clock, AnalysisOptions};
+
+    #[test]
+    fn delays() {
+        assert_eq!(Ok(0.01), parse_duration("10ms"));
+        assert_eq!(Ok(500e-6), parse_duration("500 us"));
+        assert!(parse_duration("soon").is_err());
+
+        let clock = parse_clock("11.0592MHz").unwrap();
+        let target = (0.01 / cycle_time(1, clock)).round() as usize;
+        assert_eq!(9216, target);
+        let delay = Delay::of("DELAY", target).unwrap();
+        assert_eq!(target, delay.cycles);
+        assert_eq!(2, delay.counts.len());
+        let rows = analyze(&delay.source(), &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(Some(String::from("DELAY_1")), rows[1].label);
+
+        let delay = Delay::of("WAIT", 9).unwrap();
+        assert_eq!((vec![], 5), (delay.counts.clone(), delay.padding));
+        assert_eq!("WAIT: NOP\n    NOP\n    NOP\n    NOP\n    NOP\n    RET\n", delay.source());
+        assert!(Delay::of("WAIT", 3).is_err());
+        assert!(Delay::of("WAIT", 1 << 26).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::literal::LiteralDialect;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// Keil A51.
+    #[default]
+    A51,
+    /// ASEM-51, an absolute Intel ASM51 compatible assembler.
+    Asem51,
+    /// SDCC's `sdas8051`/`asx8051`, with dotted directives.
+    Sdcc,
+}
+
+impl Dialect {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "a51" => Some(Dialect::A51),
+            "asem51" | "asem-51" => Some(Dialect::Asem51),
+            "sdcc" | "asx8051" | "sdas8051" => Some(Dialect::Sdcc),
+            _ => None,
+        }
+    }
+
+    pub fn literals(&self) -> LiteralDialect {
+        match self {
+            Dialect::A51 | Dialect::Asem51 => LiteralDialect::Intel,
+            Dialect::Sdcc => LiteralDialect::C,
+        }
+    }
+
+    /// Directives that only control the assembler and take no code or data space.
+    pub fn ignored_directives(&self) -> &'static [&'static str] {
+        match self {
+            Dialect::A51 => &[
+                "NAME", "PUBLIC", "EXTRN", "EXTERN", "USING", "SEGMENT", "RSEG", "CSEG", "DSEG",
+                "XSEG", "BSEG", "ISEG", "INCLUDE",
+            ],
+            Dialect::Asem51 => &["NAME", "USING", "CSEG", "DSEG", "XSEG", "BSEG", "ISEG", "INCLUDE"],
+            Dialect::Sdcc => &[],
+        }
+    }
+
+    /// Directives of the form `NAME directive value` that define a symbol.
+    pub fn symbol_directives(&self) -> &'static [&'static str] {
+        match self {
+            Dialect::A51 | Dialect::Asem51 => {
+                &["EQU", "SET", "DATA", "IDATA", "XDATA", "BIT", "CODE"]
+            }
+            Dialect::Sdcc => &["EQU"],
+        }
+    }
+
+    /// Rewrites a statement into the A51 spelling the analysis understands, e.g. `.db 1`
+    /// into `DB 1` or `x = 5` into `x EQU 5`.
+    ///
+    /// The mnemonic is always separated from its operands by a single space, so tab
+    /// separated compiler output tokenizes like hand written code.
+    pub fn canonical(&self, statement: &str) -> String {
+        let statement = statement.trim();
+        let (name, args) = statement
+            .split_once(char::is_whitespace)
+            .map(|(name, args)| (name, args.trim()))
+            .unwrap_or((statement, ""));
+        let join = |name: &str, args: &str| match args.is_empty() {
+            true => name.to_string(),
+            false => format!("{} {}", name, args),
+        };
+        if *self != Dialect::Sdcc {
+            return join(name, args);
+        }
+        if let Some((symbol, value)) = statement.split_once('=') {
+            let symbol = symbol.trim();
+            if !symbol.is_empty() && !symbol.contains(char::is_whitespace) {
+                return format!("{} EQU {}", symbol, value.trim_start_matches('=').trim());
+            }
+        }
+        let renamed = match name.to_ascii_lowercase().as_str() {
+            ".org" => "ORG",
+            ".db" | ".byte" | ".fcb" | ".ascii" => "DB",
+            ".dw" | ".word" | ".fdb" => "DW",
+            ".ds" | ".blkb" | ".rmb" => "DS",
+            ".asciz" => return format!("DB {}, 0", args),
+            ".equ" => {
+                return match args.split_once(',') {
+                    Some((symbol, value)) => format!("{} EQU {}", symbol.trim(), value.trim()),
+                    None => join(name, args),
+                };
+            }
+            _ => name,
+        };
+        join(renamed, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sdcc_directives() {
+        let sdcc = Dialect::Sdcc;
+        assert_eq!("DB 1, 2", sdcc.canonical(".db 1, 2"));
+        assert_eq!("DB \"hi\", 0", sdcc.canonical(".asciz \"hi\""));
+        assert_eq!("_x EQU 0x30", sdcc.canonical("_x = 0x30"));
+        assert_eq!("ar7 EQU 0x07", sdcc.canonical(".equ ar7, 0x07"));
+        assert_eq!("mov a,r7", sdcc.canonical("mov\ta,r7"));
+        assert_eq!(".area CSEG (CODE)", sdcc.canonical(".area\tCSEG (CODE)"));
+    }
+}
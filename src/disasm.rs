@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    encoding::{get_bit_map, get_sfr_map, operand_size, OPCODES},
+    literal::intel_hex,
+};
+
+/// Bytes of a program image by address.
+pub type Image = BTreeMap<usize, u8>;
+
+/// The image of a raw binary, loaded at address 0.
+pub fn image_of_binary(bytes: &[u8]) -> Image {
+    bytes.iter().copied().enumerate().collect()
+}
+
+/// Parses an Intel HEX file, checking the checksum of every record.
+pub fn parse_hex(text: &str) -> Result<Image, String> {
+    let mut res = Image::new();
+    let mut base = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let problem = |message: &str| format!("line {}: {}", index + 1, message);
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| problem("a record must start with `:`"))?;
+        let bytes = (0..digits.len() / 2)
+            .map(|at| u8::from_str_radix(digits.get(at * 2..at * 2 + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()
+            .filter(|bytes| digits.len() % 2 == 0 && bytes.len() >= 5)
+            .ok_or_else(|| problem("a record must be an even number of hex digits"))?;
+        let length = bytes[0] as usize;
+        if bytes.len() != length + 5 {
+            return Err(problem("the record length does not match its data"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(problem("checksum mismatch"));
+        }
+        let address = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        let data = &bytes[4..4 + length];
+        let value = data.iter().fold(0, |value, byte| value << 8 | *byte as usize);
+        match bytes[3] {
+            0x00 => {
+                for (offset, byte) in data.iter().enumerate() {
+                    res.insert(base + address + offset, *byte);
+                }
+            }
+            0x01 => break,
+            0x02 => base = value << 4,
+            0x04 => base = value << 16,
+            0x03 | 0x05 => {}
+            kind => return Err(problem(&format!("unknown record type {:02X}", kind))),
+        }
+    }
+    Ok(res)
+}
+
+/// The `OPCODES` entry an opcode byte belongs to, matching register numbers and the page
+/// bits of `AJMP` and `ACALL` in the opcode.
+fn lookup(code: u8) -> Option<(&'static str, &'static [&'static str])> {
+    OPCODES
+        .iter()
+        .find(|(_, kinds, base)| {
+            let mask = match *kinds {
+                kinds if kinds.contains(&"addr11") => 0x1F,
+                kinds if kinds.contains(&"Rn") => 0xF8,
+                kinds if kinds.contains(&"@Ri") => 0xFE,
+                _ => 0xFF,
+            };
+            code & mask == *base
+        })
+        .map(|(name, kinds, _)| (*name, *kinds))
+}
+
+enum Operand {
+    Text(String),
+    /// A code address, written as a label when an instruction starts there.
+    Target(usize),
+}
+
+/// A decoded instruction, or a `DB` for a byte that is none.
+struct Decoded {
+    address: usize,
+    length: usize,
+    name: &'static str,
+    operands: Vec<Operand>,
+}
+
+/// Decodes the instruction at `address`, `None` for an undefined opcode or one cut off
+/// by the end of the image.
+fn decode(image: &Image, address: usize) -> Option<Decoded> {
+    let code = image[&address];
+    let (name, kinds) = lookup(code)?;
+    let length = 1 + kinds.iter().map(|kind| operand_size(kind)).sum::<usize>();
+    let bytes = (address..address + length)
+        .map(|at| image.get(&at).copied())
+        .collect::<Option<Vec<_>>>()?;
+    let next = address + length;
+    let sfrs = get_sfr_map()
+        .into_iter()
+        .filter(|(_, value)| *value >= 0x80)
+        .map(|(name, value)| (value as usize, name))
+        .collect::<HashMap<_, _>>();
+    let bits = get_bit_map()
+        .into_iter()
+        .map(|(name, value)| (value as usize, name))
+        .collect::<HashMap<_, _>>();
+
+    // Operand bytes in the order of `kinds`, MOV direct, direct storing its source first.
+    let mut fields = vec![];
+    let mut at = 1;
+    for kind in kinds {
+        fields.push(&bytes[at..at + operand_size(kind)]);
+        at += operand_size(kind);
+    }
+    if name == "MOV" && kinds == ["addr1B", "addr1B"] {
+        fields.swap(0, 1);
+    }
+    let operands = kinds
+        .iter()
+        .zip(fields)
+        .map(|(kind, field)| {
+            let byte = field.first().copied().unwrap_or_default() as usize;
+            let word = field.iter().fold(0, |value, byte| value << 8 | *byte as usize);
+            match *kind {
+                "Rn" => Operand::Text(format!("R{}", code & 7)),
+                "@Ri" => Operand::Text(format!("@R{}", code & 1)),
+                "imm1B" => Operand::Text(format!("#{}", intel_hex(byte, 2))),
+                "imm2B" => Operand::Text(format!("#{}", intel_hex(word, 4))),
+                "addr1B" => Operand::Text(sfrs.get(&byte).cloned().unwrap_or_else(|| intel_hex(byte, 2))),
+                "bit" | "/bit" => {
+                    let bit = match (bits.get(&byte), sfrs.get(&(byte & 0xF8))) {
+                        (Some(name), _) => name.clone(),
+                        (None, Some(sfr)) if byte >= 0x80 => format!("{}.{}", sfr, byte & 7),
+                        _ => intel_hex(byte, 2),
+                    };
+                    match *kind {
+                        "/bit" => Operand::Text(format!("/{}", bit)),
+                        _ => Operand::Text(bit),
+                    }
+                }
+                "rel1B" => Operand::Target(next.wrapping_add_signed(byte as u8 as i8 as isize) & 0xFFFF),
+                "addr11" => Operand::Target(next & 0xF800 | (code as usize >> 5) << 8 | byte),
+                "addr2B" => Operand::Target(word),
+                kind => Operand::Text(kind.to_string()),
+            }
+        })
+        .collect();
+    Some(Decoded {
+        address,
+        length,
+        name,
+        operands,
+    })
+}
+
+/// Disassembles the image into source, one `ORG` per run of contiguous bytes and a label
+/// `Lxxxx` at every jump or call target that starts an instruction.
+///
+/// The image is decoded linearly, so data between code is shown as instructions where its
+/// bytes happen to be valid opcodes.
+pub fn disassemble(image: &Image) -> String {
+    let mut decoded = vec![];
+    let mut address = image.keys().next().copied();
+    while let Some(at) = address {
+        let instruction = decode(image, at).unwrap_or_else(|| Decoded {
+            address: at,
+            length: 1,
+            name: "DB",
+            operands: vec![Operand::Text(intel_hex(image[&at] as usize, 2))],
+        });
+        address = image.range(at + instruction.length..).next().map(|(address, _)| *address);
+        decoded.push(instruction);
+    }
+
+    let starts = decoded.iter().map(|decoded| decoded.address).collect::<HashSet<_>>();
+    let targets = decoded
+        .iter()
+        .flat_map(|decoded| &decoded.operands)
+        .filter_map(|operand| match operand {
+            Operand::Target(target) if starts.contains(target) => Some(*target),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut res = String::new();
+    let mut end = None;
+    for instruction in &decoded {
+        if end != Some(instruction.address) {
+            res.push_str(&format!("{:<8}ORG {}\n", "", intel_hex(instruction.address, 4)));
+        }
+        let operands = instruction
+            .operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Text(text) => text.clone(),
+                Operand::Target(target) if targets.contains(target) => format!("L{:04X}", target),
+                Operand::Target(target) => intel_hex(*target, 4),
+            })
+            .collect::<Vec<_>>();
+        let label = match targets.contains(&instruction.address) {
+            true => format!("L{:04X}:", instruction.address),
+            false => String::new(),
+        };
+        let statement = match operands.is_empty() {
+            true => instruction.name.to_string(),
+            false => format!("{} {}", instruction.name, operands.join(", ")),
+        };
+        res.push_str(&format!("{:<8}{}\n", label, statement));
+        end = Some(instruction.address + instruction.length);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn round_trip() {
+        let source = "ORG 0\nLJMP MAIN\nORG 30H\nMAIN: MOV SP, #6FH\nMOV 30H, 31H\nLOOP: JNB TI, LOOP\nSETB P1.3\nACALL WORK\nCJNE R3, #10, LOOP\nSJMP MAIN\nWORK: MOVC A, @A+DPTR\nMOV DPTR, #1234H\nRET";
+        let options = AnalysisOptions::default();
+        let rows = analyze(source, &options);
+        let mut image = Image::new();
+        for row in &rows {
+            for (offset, byte) in row.encoding.iter().flatten().enumerate() {
+                image.insert(row.address + offset, *byte);
+            }
+        }
+        image.insert(0x50, 0xA5);
+
+        let text = disassemble(&image);
+        assert!(text.starts_with("        ORG 0000H\n        LJMP L0030\n        ORG 0030H\nL0030:  MOV SP, #6FH\n"));
+        assert!(text.contains("        MOV 30H, 31H\nL0036:  JNB TI, L0036\n        SETB P1.3\n        ACALL L0042\n"));
+        assert!(text.contains("        ORG 0050H\n        DB 0A5H\n"));
+        let decoded = analyze(&text, &options);
+        assert!(decoded.iter().all(|row| !row.has_error()));
+        let encoding = |rows: &[crate::report::Row]| {
+            rows.iter()
+                .filter_map(|row| Some((row.address, row.encoding.clone()?)))
+                .filter(|(address, _)| *address < 0x50)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(encoding(&rows), encoding(&decoded));
+    }
+
+    #[test]
+    fn opcode_map() {
+        // A5H is the only opcode the 8051 leaves undefined.
+        assert!((0..=0xFF).filter(|&code| code != 0xA5).all(|code| lookup(code).is_some()));
+        assert!(lookup(0xA5).is_none());
+
+        let image = image_of_binary(&[0x15, 0x30, 0xB0, 0x92, 0xA0, 0x99, 0x80, 0xF8, 0x22, 0xA5]);
+        let text = disassemble(&image);
+        assert_eq!(
+            "        ORG 0000H\nL0000:  DEC 30H\n        ANL C, /P1.2\n        ORL C, /TI\n        SJMP L0000\n        RET\n        DB 0A5H\n",
+            text
+        );
+        let rows = analyze(&text, &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        let bytes = rows.iter().flat_map(|row| row.encoding.iter().flatten()).copied();
+        assert!(bytes.eq(image.into_values()));
+    }
+
+    #[test]
+    fn hex_records() {
+        let image = parse_hex(":03000000020030CB\n:0100300022AD\n:00000001FF\n").unwrap();
+        let bytes = image.into_iter().collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0x02), (1, 0x00), (2, 0x30), (0x30, 0x22)], bytes);
+        assert_eq!(Err(String::from("line 1: checksum mismatch")), parse_hex(":03000000020030CC"));
+        assert!(parse_hex("020030").is_err());
+    }
+}
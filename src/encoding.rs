@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use crate::{literal::parse_number, symbols::SymbolTable};
+
+/// Base opcode of every variant in `INSTRUCTIONS`. For `Rn` and `@Ri` operands the
+/// register number is added to the base opcode.
+pub const OPCODES: &[(&str, &[&str], u8)] = &[
+    ("NOP", &[], 0x00),
+    ("AJMP", &["addr11"], 0x01),
+    ("LJMP", &["addr2B"], 0x02),
+    ("RR", &["A"], 0x03),
+    ("INC", &["A"], 0x04),
+    ("INC", &["addr1B"], 0x05),
+    ("INC", &["@Ri"], 0x06),
+    ("INC", &["Rn"], 0x08),
+    ("JBC", &["bit", "rel1B"], 0x10),
+    ("ACALL", &["addr11"], 0x11),
+    ("LCALL", &["addr2B"], 0x12),
+    ("RRC", &["A"], 0x13),
+    ("DEC", &["A"], 0x14),
+    ("DEC", &["addr1B"], 0x15),
+    ("DEC", &["@Ri"], 0x16),
+    ("DEC", &["Rn"], 0x18),
+    ("JB", &["bit", "rel1B"], 0x20),
+    ("RET", &[], 0x22),
+    ("RL", &["A"], 0x23),
+    ("ADD", &["A", "imm1B"], 0x24),
+    ("ADD", &["A", "addr1B"], 0x25),
+    ("ADD", &["A", "@Ri"], 0x26),
+    ("ADD", &["A", "Rn"], 0x28),
+    ("JNB", &["bit", "rel1B"], 0x30),
+    ("RETI", &[], 0x32),
+    ("RLC", &["A"], 0x33),
+    ("ADDC", &["A", "imm1B"], 0x34),
+    ("ADDC", &["A", "addr1B"], 0x35),
+    ("ADDC", &["A", "@Ri"], 0x36),
+    ("ADDC", &["A", "Rn"], 0x38),
+    ("JC", &["rel1B"], 0x40),
+    ("ORL", &["addr1B", "A"], 0x42),
+    ("ORL", &["addr1B", "imm1B"], 0x43),
+    ("ORL", &["A", "imm1B"], 0x44),
+    ("ORL", &["A", "addr1B"], 0x45),
+    ("ORL", &["A", "@Ri"], 0x46),
+    ("ORL", &["A", "Rn"], 0x48),
+    ("JNC", &["rel1B"], 0x50),
+    ("ANL", &["addr1B", "A"], 0x52),
+    ("ANL", &["addr1B", "imm1B"], 0x53),
+    ("ANL", &["A", "imm1B"], 0x54),
+    ("ANL", &["A", "addr1B"], 0x55),
+    ("ANL", &["A", "@Ri"], 0x56),
+    ("ANL", &["A", "Rn"], 0x58),
+    ("JZ", &["rel1B"], 0x60),
+    ("XRL", &["addr1B", "A"], 0x62),
+    ("XRL", &["addr1B", "imm1B"], 0x63),
+    ("XRL", &["A", "imm1B"], 0x64),
+    ("XRL", &["A", "addr1B"], 0x65),
+    ("XRL", &["A", "@Ri"], 0x66),
+    ("XRL", &["A", "Rn"], 0x68),
+    ("JNZ", &["rel1B"], 0x70),
+    ("ORL", &["C", "bit"], 0x72),
+    ("JMP", &["@A+DPTR"], 0x73),
+    ("MOV", &["A", "imm1B"], 0x74),
+    ("MOV", &["addr1B", "imm1B"], 0x75),
+    ("MOV", &["@Ri", "imm1B"], 0x76),
+    ("MOV", &["Rn", "imm1B"], 0x78),
+    ("SJMP", &["rel1B"], 0x80),
+    ("ANL", &["C", "bit"], 0x82),
+    ("MOVC", &["A", "@A+PC"], 0x83),
+    ("DIV", &["AB"], 0x84),
+    ("MOV", &["addr1B", "addr1B"], 0x85),
+    ("MOV", &["addr1B", "@Ri"], 0x86),
+    ("MOV", &["addr1B", "Rn"], 0x88),
+    ("MOV", &["DPTR", "imm2B"], 0x90),
+    ("MOV", &["bit", "C"], 0x92),
+    ("MOVC", &["A", "@A+DPTR"], 0x93),
+    ("SUBB", &["A", "imm1B"], 0x94),
+    ("SUBB", &["A", "addr1B"], 0x95),
+    ("SUBB", &["A", "@Ri"], 0x96),
+    ("SUBB", &["A", "Rn"], 0x98),
+    ("ORL", &["C", "/bit"], 0xA0),
+    ("MOV", &["C", "bit"], 0xA2),
+    ("INC", &["DPTR"], 0xA3),
+    ("MUL", &["AB"], 0xA4),
+    ("MOV", &["@Ri", "addr1B"], 0xA6),
+    ("MOV", &["Rn", "addr1B"], 0xA8),
+    ("ANL", &["C", "/bit"], 0xB0),
+    ("CPL", &["bit"], 0xB2),
+    ("CPL", &["C"], 0xB3),
+    ("CJNE", &["A", "imm1B", "rel1B"], 0xB4),
+    ("CJNE", &["A", "addr1B", "rel1B"], 0xB5),
+    ("CJNE", &["@Ri", "imm1B", "rel1B"], 0xB6),
+    ("CJNE", &["Rn", "imm1B", "rel1B"], 0xB8),
+    ("PUSH", &["addr1B"], 0xC0),
+    ("CLR", &["bit"], 0xC2),
+    ("CLR", &["C"], 0xC3),
+    ("SWAP", &["A"], 0xC4),
+    ("XCH", &["A", "addr1B"], 0xC5),
+    ("XCH", &["A", "@Ri"], 0xC6),
+    ("XCH", &["A", "Rn"], 0xC8),
+    ("POP", &["addr1B"], 0xD0),
+    ("SETB", &["bit"], 0xD2),
+    ("SETB", &["C"], 0xD3),
+    ("DA", &["A"], 0xD4),
+    ("DJNZ", &["addr1B", "rel1B"], 0xD5),
+    ("XCHD", &["A", "@Ri"], 0xD6),
+    ("DJNZ", &["Rn", "rel1B"], 0xD8),
+    ("MOVX", &["A", "@DPTR"], 0xE0),
+    ("MOVX", &["A", "@Ri"], 0xE2),
+    ("CLR", &["A"], 0xE4),
+    ("MOV", &["A", "addr1B"], 0xE5),
+    ("MOV", &["A", "@Ri"], 0xE6),
+    ("MOV", &["A", "Rn"], 0xE8),
+    ("MOVX", &["@DPTR", "A"], 0xF0),
+    ("MOVX", &["@Ri", "A"], 0xF2),
+    ("CPL", &["A"], 0xF4),
+    ("MOV", &["addr1B", "A"], 0xF5),
+    ("MOV", &["@Ri", "A"], 0xF6),
+    ("MOV", &["Rn", "A"], 0xF8),
+];
+
+pub fn get_sfr_map() -> HashMap<String, i64> {
+    let mut res = HashMap::new();
+    res.insert(String::from("P0"), 0x80);
+    res.insert(String::from("SP"), 0x81);
+    res.insert(String::from("DPL"), 0x82);
+    res.insert(String::from("DPH"), 0x83);
+    res.insert(String::from("PCON"), 0x87);
+    res.insert(String::from("TCON"), 0x88);
+    res.insert(String::from("TMOD"), 0x89);
+    res.insert(String::from("TL0"), 0x8A);
+    res.insert(String::from("TL1"), 0x8B);
+    res.insert(String::from("TH0"), 0x8C);
+    res.insert(String::from("TH1"), 0x8D);
+    res.insert(String::from("P1"), 0x90);
+    res.insert(String::from("SCON"), 0x98);
+    res.insert(String::from("SBUF"), 0x99);
+    res.insert(String::from("P2"), 0xA0);
+    res.insert(String::from("IE"), 0xA8);
+    res.insert(String::from("P3"), 0xB0);
+    res.insert(String::from("IP"), 0xB8);
+    res.insert(String::from("PSW"), 0xD0);
+    res.insert(String::from("ACC"), 0xE0);
+    res.insert(String::from("B"), 0xF0);
+    // Register bank 0 by absolute address, as used by `PUSH AR7`.
+    for n in 0..8 {
+        res.insert(format!("AR{}", n), n);
+    }
+    res
+}
+
+pub fn get_bit_map() -> HashMap<String, i64> {
+    let mut res = HashMap::new();
+    res.insert(String::from("IT0"), 0x88);
+    res.insert(String::from("IE0"), 0x89);
+    res.insert(String::from("IT1"), 0x8A);
+    res.insert(String::from("IE1"), 0x8B);
+    res.insert(String::from("TR0"), 0x8C);
+    res.insert(String::from("TF0"), 0x8D);
+    res.insert(String::from("TR1"), 0x8E);
+    res.insert(String::from("TF1"), 0x8F);
+    res.insert(String::from("RI"), 0x98);
+    res.insert(String::from("TI"), 0x99);
+    res.insert(String::from("RB8"), 0x9A);
+    res.insert(String::from("TB8"), 0x9B);
+    res.insert(String::from("REN"), 0x9C);
+    res.insert(String::from("SM2"), 0x9D);
+    res.insert(String::from("SM1"), 0x9E);
+    res.insert(String::from("SM0"), 0x9F);
+    res.insert(String::from("EX0"), 0xA8);
+    res.insert(String::from("ET0"), 0xA9);
+    res.insert(String::from("EX1"), 0xAA);
+    res.insert(String::from("ET1"), 0xAB);
+    res.insert(String::from("ES"), 0xAC);
+    res.insert(String::from("EA"), 0xAF);
+    res.insert(String::from("PX0"), 0xB8);
+    res.insert(String::from("PT0"), 0xB9);
+    res.insert(String::from("PX1"), 0xBA);
+    res.insert(String::from("PT1"), 0xBB);
+    res.insert(String::from("PS"), 0xBC);
+    res.insert(String::from("P"), 0xD0);
+    res.insert(String::from("OV"), 0xD2);
+    res.insert(String::from("RS0"), 0xD3);
+    res.insert(String::from("RS1"), 0xD4);
+    res.insert(String::from("F0"), 0xD5);
+    res.insert(String::from("AC"), 0xD6);
+    res.insert(String::from("CY"), 0xD7);
+    res
+}
+
+pub fn opcode(name: &str, kinds: &[String]) -> Option<u8> {
+    OPCODES
+        .iter()
+        .find(|(inst, ops, _)| inst.eq_ignore_ascii_case(name) && ops.iter().eq(kinds.iter()))
+        .map(|(_, _, code)| *code)
+}
+
+/// Resolves a numeric operand: a literal, an SFR name or a user symbol.
+pub fn resolve(operand: &str, symbols: &SymbolTable) -> Option<i64> {
+    let operand = operand.trim_start_matches('#');
+    if let Some(operand) = operand.strip_prefix('<') {
+        return resolve(operand, symbols).map(|value| value & 0xFF);
+    }
+    if let Some(operand) = operand.strip_prefix('>') {
+        return resolve(operand, symbols).map(|value| (value >> 8) & 0xFF);
+    }
+    parse_number(operand)
+        .or_else(|| get_sfr_map().get(&operand.to_ascii_uppercase()).copied())
+        .or_else(|| symbols.get(operand))
+}
+
+/// Resolves a bit operand: a literal bit address, a named flag or `BYTE.n`.
+pub fn resolve_bit(operand: &str, symbols: &SymbolTable) -> Option<i64> {
+    if let Some((byte, bit)) = operand.split_once('.') {
+        let base = resolve(byte, symbols)?;
+        let bit = parse_number(bit).filter(|bit| (0..8).contains(bit))?;
+        return match base {
+            0x20..=0x2F => Some((base - 0x20) * 8 + bit),
+            0x80..=0xFF if base % 8 == 0 => Some(base + bit),
+            _ => None,
+        };
+    }
+    get_bit_map()
+        .get(&operand.to_ascii_uppercase())
+        .copied()
+        .or_else(|| resolve(operand, symbols))
+}
+
+/// Bytes an operand of `kind` takes after the opcode.
+pub fn operand_size(kind: &str) -> usize {
+    match kind {
+        "imm2B" | "addr2B" => 2,
+        "imm1B" | "addr1B" | "addr11" | "rel1B" | "bit" | "/bit" => 1,
+        _ => 0,
+    }
+}
+
+/// Encodes a matched instruction variant into machine code.
+///
+/// Returns `None` when an operand cannot be resolved or does not fit its field.
+pub fn encode(
+    name: &str,
+    kinds: &[String],
+    operands: &[&str],
+    address: usize,
+    symbols: &SymbolTable,
+) -> Option<Vec<u8>> {
+    let mut code = opcode(name, kinds)?;
+    let length = 1 + kinds.iter().map(|kind| operand_size(kind)).sum::<usize>();
+    let mut res = vec![];
+    for (kind, operand) in kinds.iter().zip(operands) {
+        match kind.as_str() {
+            "Rn" | "@Ri" => {
+                code += operand.trim_start_matches('@')[1..].parse::<u8>().ok()?;
+            }
+            "imm1B" => {
+                let value = resolve(operand, symbols).filter(|v| (-128..=255).contains(v))?;
+                res.push(value as u8);
+            }
+            "imm2B" | "addr2B" => {
+                let value = resolve(operand, symbols).filter(|v| (0..=0xFFFF).contains(v))?;
+                res.extend([(value >> 8) as u8, value as u8]);
+            }
+            "addr11" => {
+                let value = resolve(operand, symbols)?;
+                if value >> 11 != ((address + length) >> 11) as i64 {
+                    return None;
+                }
+                code |= ((value >> 8) as u8 & 0x07) << 5;
+                res.push(value as u8);
+            }
+            "addr1B" => {
+                let value = resolve(operand, symbols).filter(|v| (0..=0xFF).contains(v))?;
+                res.push(value as u8);
+            }
+            "bit" | "/bit" => {
+                let operand = operand.trim_start_matches('/');
+                let value = resolve_bit(operand, symbols).filter(|v| (0..=0xFF).contains(v))?;
+                res.push(value as u8);
+            }
+            "rel1B" => {
+                let offset = resolve(operand, symbols)? - (address + length) as i64;
+                if !(-128..=127).contains(&offset) {
+                    return None;
+                }
+                res.push(offset as u8);
+            }
+            _ => {}
+        }
+    }
+    // MOV direct, direct stores the source address first.
+    if name.eq_ignore_ascii_case("MOV") && kinds.iter().all(|kind| kind == "addr1B") {
+        res.swap(0, 1);
+    }
+    res.insert(0, code);
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(kinds: &[&str]) -> Vec<String> {
+        kinds.iter().map(|kind| kind.to_string()).collect()
+    }
+
+    #[test]
+    fn mov_dptr() {
+        let code = encode(
+            "MOV",
+            &kinds(&["DPTR", "imm2B"]),
+            &["DPTR", "#200H"],
+            0,
+            &SymbolTable::default(),
+        );
+        assert_eq!(Some(vec![0x90, 0x02, 0x00]), code);
+    }
+
+    #[test]
+    fn ljmp() {
+        let symbols = SymbolTable::from([("MAIN", 0x1234)]);
+        let code = encode("LJMP", &kinds(&["addr2B"]), &["MAIN"], 0, &symbols);
+        assert_eq!(Some(vec![0x02, 0x12, 0x34]), code);
+    }
+
+    #[test]
+    fn jnb_backwards() {
+        let symbols = SymbolTable::from([("WAIT", 0x10)]);
+        let code = encode(
+            "jnb",
+            &kinds(&["bit", "rel1B"]),
+            &["ti", "wait"],
+            0x10,
+            &symbols,
+        );
+        assert_eq!(Some(vec![0x30, 0x99, 0xFD]), code);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use regex::Regex;
+
+use crate::{
+    literal::diagnose,
+    parser::{get_all_inst_variants, get_dialect_regex, get_dialect_skip_list},
+    report::{AnalysisOptions, Row},
+};
+
+pub enum Failure {
+    OperandCount { expected: usize, found: usize },
+    Operand { index: usize, operand: String, kind: String },
+}
+
+pub struct Attempt {
+    pub variant: Vec<String>,
+    pub failure: Option<Failure>,
+}
+
+pub struct Explanation {
+    /// The included file the line comes from, `None` for the analyzed file itself.
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub statement: String,
+    /// Set when the statement is a directive from the skip list.
+    pub directive: bool,
+    pub known: bool,
+    pub attempts: Vec<Attempt>,
+    pub suggestion: Option<String>,
+    /// A problem with the line other than its instruction, such as a missing include file.
+    pub problem: Option<String>,
+}
+
+impl Explanation {
+    pub fn matched(&self) -> Option<&Attempt> {
+        self.attempts.iter().find(|attempt| attempt.failure.is_none())
+    }
+}
+
+/// The operand syntax a variant kind stands for, as written in the 8051 manuals.
+pub fn operand_form(kind: &str) -> &str {
+    match kind {
+        "imm1B" => "#data",
+        "imm2B" => "#data16",
+        "addr1B" => "direct",
+        "addr11" => "addr11",
+        "addr2B" => "addr16",
+        "rel1B" => "rel",
+        _ => kind,
+    }
+}
+
+pub fn variant_form(name: &str, variant: &[String]) -> String {
+    if variant.is_empty() {
+        return name.to_string();
+    }
+    let operands = variant
+        .iter()
+        .map(|kind| operand_form(kind))
+        .collect::<Vec<_>>();
+    format!("{} {}", name, operands.join(", "))
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+fn closest_mnemonic(name: &str, all_inst: &HashMap<String, Vec<Vec<String>>>) -> Option<String> {
+    all_inst
+        .keys()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Coarse syntactic shape of an operand: immediate, indirect, register or anything else.
+fn shape(operand: &str) -> char {
+    if operand.starts_with('#') {
+        '#'
+    } else if operand.starts_with('@') {
+        '@'
+    } else if operand.len() > 1
+        && operand.starts_with('R')
+        && operand[1..].chars().all(|c| c.is_ascii_digit())
+    {
+        'R'
+    } else {
+        '_'
+    }
+}
+
+/// Whether `operand` is written the way `kind` is, e.g. `#` for immediates.
+fn looks_like(kind: &str, operand: &str) -> bool {
+    let expected = match kind {
+        "imm1B" | "imm2B" => '#',
+        "@Ri" | "@DPTR" | "@A+DPTR" | "@A+PC" => '@',
+        "Rn" => 'R',
+        _ => '_',
+    };
+    shape(operand) == expected
+}
+
+/// Ranks how far `operands` are from `variant`, counting failed operands first and
+/// differently shaped ones second.
+fn distance(variant: &[String], operands: &[&str], regex_map: &HashMap<String, Regex>) -> (usize, usize) {
+    if variant.len() != operands.len() {
+        return (usize::MAX, usize::MAX);
+    }
+    let failed = variant
+        .iter()
+        .zip(operands)
+        .filter(|(kind, operand)| !regex_map.get(*kind).unwrap().is_match(operand))
+        .collect::<Vec<_>>();
+    let unlike = failed
+        .iter()
+        .filter(|(kind, operand)| !looks_like(kind, operand))
+        .count();
+    (failed.len(), unlike)
+}
+
+pub fn explain(
+    line: usize,
+    statement: &str,
+    all_inst: &HashMap<String, Vec<Vec<String>>>,
+    regex_map: &HashMap<String, Regex>,
+    skip_list: &[Regex],
+) -> Explanation {
+    let mut res = Explanation {
+        file: None,
+        line,
+        statement: statement.to_string(),
+        directive: skip_list.iter().any(|reg_pat| reg_pat.is_match(statement)),
+        known: false,
+        attempts: vec![],
+        suggestion: None,
+        problem: None,
+    };
+    if statement.is_empty() || res.directive {
+        return res;
+    }
+    let (name, raw_operands) = statement.split_once(' ').unwrap_or((statement, ""));
+    let name = name.to_ascii_uppercase();
+    let operands = raw_operands
+        .split(',')
+        .map(str::trim)
+        .filter(|op| !op.is_empty())
+        .collect::<Vec<_>>();
+    let Some(variants) = all_inst.get(&name) else {
+        res.suggestion = closest_mnemonic(&name, all_inst);
+        return res;
+    };
+    res.known = true;
+    let variants = if variants.is_empty() { vec![vec![]] } else { variants.clone() };
+    for variant in &variants {
+        let failure = if variant.len() != operands.len() {
+            Some(Failure::OperandCount {
+                expected: variant.len(),
+                found: operands.len(),
+            })
+        } else {
+            variant
+                .iter()
+                .zip(&operands)
+                .enumerate()
+                .find(|(_, (kind, operand))| !regex_map.get(*kind).unwrap().is_match(operand))
+                .map(|(index, (kind, operand))| Failure::Operand {
+                    index,
+                    operand: operand.to_string(),
+                    kind: kind.clone(),
+                })
+        };
+        let matched = failure.is_none();
+        res.attempts.push(Attempt {
+            variant: variant.clone(),
+            failure,
+        });
+        if matched {
+            return res;
+        }
+    }
+    res.suggestion = variants
+        .iter()
+        .min_by_key(|variant| distance(variant, &operands, regex_map))
+        .map(|variant| variant_form(&name, variant));
+    res
+}
+
+pub fn explain_rows(rows: &[Row], options: &AnalysisOptions) -> Vec<Explanation> {
+    let all_inst_map = get_all_inst_variants();
+    let regex_map = get_dialect_regex(options.dialect, options.literals);
+    let skip_list = get_dialect_skip_list(options.dialect);
+    rows.iter()
+        .filter(|row| !row.statement.is_empty())
+        .map(|row| {
+            let statement = options.dialect.canonical(&row.statement);
+            Explanation {
+                file: row.file.clone(),
+                problem: row.problem.clone(),
+                ..explain(row.line, &statement, &all_inst_map, &regex_map, &skip_list)
+            }
+        })
+        .collect()
+}
+
+pub fn write_explanations<W: Write>(
+    rows: &[Row],
+    options: &AnalysisOptions,
+    mut writer: W,
+) -> io::Result<()> {
+    for explanation in explain_rows(rows, options) {
+        match &explanation.file {
+            Some(file) => writeln!(
+                writer,
+                "{}:{}: {}",
+                file.display(),
+                explanation.line,
+                explanation.statement
+            )?,
+            None => writeln!(writer, "line {}: {}", explanation.line, explanation.statement)?,
+        }
+        if let Some(problem) = &explanation.problem {
+            writeln!(writer, "  {}", problem)?;
+        }
+        if explanation.directive {
+            writeln!(writer, "  directive, not analyzed")?;
+        } else if !explanation.known {
+            write!(writer, "  unknown mnemonic")?;
+        }
+        let name = explanation.statement.split(' ').next().unwrap_or("");
+        for attempt in &explanation.attempts {
+            let form = variant_form(&name.to_ascii_uppercase(), &attempt.variant);
+            match &attempt.failure {
+                None => writeln!(writer, "  {:<24} matched", form)?,
+                Some(Failure::OperandCount { expected, found }) => writeln!(
+                    writer,
+                    "  {:<24} expects {} operand(s), found {}",
+                    form, expected, found
+                )?,
+                Some(Failure::Operand {
+                    index,
+                    operand,
+                    kind,
+                }) => {
+                    write!(
+                        writer,
+                        "  {:<24} operand {} `{}` is not {}",
+                        form,
+                        index + 1,
+                        operand,
+                        operand_form(kind)
+                    )?;
+                    match diagnose(operand).filter(|_| looks_like(kind, operand)) {
+                        Some(hint) => writeln!(writer, " ({})", hint)?,
+                        None => writeln!(writer)?,
+                    }
+                }
+            }
+        }
+        if explanation.known && explanation.matched().is_none() {
+            write!(writer, "  no variant matched")?;
+        }
+        match &explanation.suggestion {
+            Some(suggestion) => writeln!(writer, "; did you mean `{}`?", suggestion)?,
+            None if explanation.matched().is_none() && !explanation.directive => writeln!(writer)?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{get_regex, get_skip_list};
+
+    fn explain_line(statement: &str) -> Explanation {
+        explain(
+            1,
+            statement,
+            &get_all_inst_variants(),
+            &get_regex(),
+            &get_skip_list(),
+        )
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(Some(String::from("MOV")), explain_line("MVO A, R0").suggestion);
+        assert_eq!(Some(String::from("MOV A, Rn")), explain_line("MOV A, R8").suggestion);
+        let explanation = explain_line("MOV A, R0");
+        assert_eq!(
+            Some(&vec![String::from("A"), String::from("Rn")]),
+            explanation.matched().map(|attempt| &attempt.variant)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::report::split_statement;
+
+/// A line of the source after includes and macros are expanded.
+#[derive(Clone, Default)]
+pub struct SourceLine {
+    /// The included file the line comes from, `None` for the analyzed file itself.
+    pub file: Option<PathBuf>,
+    /// Line number within `file`.
+    pub line: usize,
+    pub text: String,
+    /// How deeply the line is nested in includes, 0 for the analyzed file.
+    pub depth: usize,
+    /// Why the include or macro on this line could not be expanded.
+    pub problem: Option<String>,
+    /// How deeply the line is nested in macro expansions, 0 outside of them.
+    pub macro_depth: usize,
+    /// Set for a macro invocation, whose expansion follows it.
+    pub invocation: bool,
+    /// Set for lines that take no part in the analysis, such as macro definitions.
+    pub skipped: bool,
+}
+
+impl SourceLine {
+    /// The lines of a source without resolving its includes.
+    pub fn plain(contents: &str) -> Vec<SourceLine> {
+        contents
+            .lines()
+            .enumerate()
+            .map(|(index, text)| SourceLine {
+                file: None,
+                line: index + 1,
+                text: text.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// Returns the file named by an include statement: `$INCLUDE (REG51.INC)` in A51 and
+/// ASEM-51, `INCLUDE defs.inc` in A51 and `.include "defs.inc"` in SDCC.
+pub fn include_target(statement: &str) -> Option<&str> {
+    let upper = statement.to_ascii_uppercase();
+    let prefix = ["$INCLUDE", ".INCLUDE", "INCLUDE"]
+        .into_iter()
+        .find(|prefix| upper.starts_with(prefix))?;
+    let rest = &statement[prefix.len()..];
+    if !rest.starts_with(|c: char| c.is_whitespace() || c == '(') {
+        return None;
+    }
+    let rest = rest.trim();
+    let name = rest
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'))
+        .unwrap_or(rest)
+        .trim()
+        .trim_matches(['"', '\'']);
+    (!name.is_empty()).then_some(name)
+}
+
+/// Identity of a file for cycle detection.
+fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+struct Expander<'a, F> {
+    search: &'a [PathBuf],
+    read: &'a F,
+    /// The chain of files currently being expanded, outermost first.
+    stack: Vec<PathBuf>,
+    res: Vec<SourceLine>,
+}
+
+impl<F: Fn(&Path) -> io::Result<String>> Expander<'_, F> {
+    fn expand(&mut self, file: Option<&Path>, dir: &Path, contents: &str) {
+        let depth = self.stack.len() - 1;
+        for (index, text) in contents.lines().enumerate() {
+            let (_, statement, _) = split_statement(text);
+            let target = include_target(statement);
+            let mut line = SourceLine {
+                file: file.map(Path::to_path_buf),
+                line: index + 1,
+                text: text.to_string(),
+                depth,
+                ..Default::default()
+            };
+            let Some(target) = target else {
+                self.res.push(line);
+                continue;
+            };
+            let found = std::iter::once(dir)
+                .chain(self.search.iter().map(PathBuf::as_path))
+                .map(|dir| dir.join(target))
+                .find_map(|path| (self.read)(&path).ok().map(|contents| (path, contents)));
+            let Some((path, contents)) = found else {
+                line.problem = Some(format!("cannot find include file `{}`", target));
+                self.res.push(line);
+                continue;
+            };
+            let id = identity(&path);
+            if let Some(start) = self.stack.iter().position(|open| *open == id) {
+                let chain = self.stack[start..]
+                    .iter()
+                    .chain([&id])
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                line.problem = Some(format!("include cycle: {}", chain.join(" -> ")));
+                self.res.push(line);
+                continue;
+            }
+            self.res.push(line);
+            self.stack.push(id);
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            self.expand(Some(&path), &dir, &contents);
+            self.stack.pop();
+        }
+    }
+}
+
+/// Expands the includes of the source at `path`, searching the directory of the including
+/// file first and then `search` in order.
+///
+/// Included lines follow the include statement, which is kept so the table shows where
+/// they came from. Missing files and include cycles are reported on the include line.
+pub fn expand<F>(path: &Path, contents: &str, search: &[PathBuf], read: &F) -> Vec<SourceLine>
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
+    let mut expander = Expander {
+        search,
+        read,
+        stack: vec![identity(path)],
+        res: vec![],
+    };
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    expander.expand(None, &dir, contents);
+    expander.res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> io::Result<String> {
+        match path.to_str().unwrap() {
+            "src/defs.inc" => Ok(String::from("LED BIT P1.0")),
+            "lib/REG.INC" => Ok(String::from("$INCLUDE (LOOP.INC)")),
+            "lib/LOOP.INC" => Ok(String::from("INCLUDE REG.INC")),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    #[test]
+    fn includes() {
+        assert_eq!(Some("REG51.INC"), include_target("$include(REG51.INC)"));
+        assert_eq!(Some("defs.inc"), include_target(".include \"defs.inc\""));
+        assert_eq!(None, include_target("INCLUDES"));
+
+        let source = "INCLUDE defs.inc\nCPL LED\n$INCLUDE (REG.INC)\nINCLUDE missing.inc";
+        let lines = expand(Path::new("src/main.asm"), source, &[PathBuf::from("lib")], &read);
+        let texts = lines
+            .iter()
+            .map(|line| (line.text.as_str(), line.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("INCLUDE defs.inc", 0),
+                ("LED BIT P1.0", 1),
+                ("CPL LED", 0),
+                ("$INCLUDE (REG.INC)", 0),
+                ("$INCLUDE (LOOP.INC)", 1),
+                ("INCLUDE REG.INC", 2),
+                ("INCLUDE missing.inc", 0),
+            ],
+            texts
+        );
+        assert_eq!(Some(PathBuf::from("src/defs.inc")), lines[1].file);
+        assert_eq!(
+            Some(String::from("include cycle: lib/REG.INC -> lib/LOOP.INC -> lib/REG.INC")),
+            lines[5].problem
+        );
+        assert_eq!(
+            Some(String::from("cannot find include file `missing.inc`")),
+            lines[6].problem
+        );
+    }
+}
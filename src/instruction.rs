@@ -52,9 +52,11 @@ pub fn get_addr_mode_map() -> HashMap<String, AddressingMode> {
     res.insert(String::from("imm1B"), AddressingMode::Immediate(false));
     res.insert(String::from("imm2B"), AddressingMode::Immediate(true));
     res.insert(String::from("addr1B"), AddressingMode::Direct(false));
+    res.insert(String::from("addr11"), AddressingMode::Direct(false));
     res.insert(String::from("addr2B"), AddressingMode::Direct(true));
     res.insert(String::from("rel1B"), AddressingMode::Direct(false));
     res.insert(String::from("bit"), AddressingMode::Direct(false));
+    res.insert(String::from("/bit"), AddressingMode::Direct(false));
     res.insert(String::from("@A+DPTR"), AddressingMode::Indexed);
     res.insert(String::from("@A+PC"), AddressingMode::Indexed);
     res
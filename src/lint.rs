@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::{
+    cfg::{flow, Cfg, EdgeKind, Flow},
+    report::Row,
+    segment::Space,
+    stack::VECTORS,
+    symbols::LabelCase,
+};
+
+pub struct Rule {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub const RULES: [Rule; 8] = [
+    Rule {
+        id: "L001",
+        name: "undefined-label",
+        description: "a jump or call targets a label that is never defined",
+    },
+    Rule {
+        id: "L002",
+        name: "unused-label",
+        description: "a label is never referenced",
+    },
+    Rule {
+        id: "L003",
+        name: "duplicate-label",
+        description: "a label is defined more than once",
+    },
+    Rule {
+        id: "L004",
+        name: "unreachable-code",
+        description: "code follows an unconditional jump or return without a label",
+    },
+    Rule {
+        id: "L005",
+        name: "falls-off-end",
+        description: "execution runs past the last instruction of the program",
+    },
+    Rule {
+        id: "L006",
+        name: "ret-in-isr",
+        description: "an interrupt routine returns with RET instead of RETI",
+    },
+    Rule {
+        id: "L007",
+        name: "unbalanced-stack",
+        description: "a routine returns with more or fewer bytes pushed than popped",
+    },
+    Rule {
+        id: "L008",
+        name: "register-bank",
+        description: "R0-R7 are written after PSW selected another register bank",
+    },
+];
+
+/// Parses a comma separated list of rule IDs or names, or `all`.
+pub fn parse_rules(text: &str) -> Result<Vec<&'static str>, String> {
+    let mut res = vec![];
+    for name in text.split(',').map(str::trim) {
+        if name.eq_ignore_ascii_case("all") {
+            res.extend(RULES.iter().map(|rule| rule.id));
+            continue;
+        }
+        let rule = RULES
+            .iter()
+            .find(|rule| rule.id.eq_ignore_ascii_case(name) || rule.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown lint rule `{}`", name))?;
+        res.push(rule.id);
+    }
+    Ok(res)
+}
+
+/// A problem a lint rule found on a line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn name(&self) -> &'static str {
+        RULES
+            .iter()
+            .find(|rule| rule.id == self.rule)
+            .map_or("", |rule| rule.name)
+    }
+}
+
+struct Linter<'a> {
+    rows: &'a [Row],
+    cfg: Cfg,
+    /// The first label at each code address.
+    labels: HashMap<usize, &'a str>,
+    res: Vec<Finding>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: &'static str, row: usize, message: String) {
+        let row = &self.rows[row];
+        self.res.push(Finding {
+            rule,
+            file: row.file.clone(),
+            line: row.line,
+            message,
+        });
+    }
+
+    fn undefined_labels(&mut self) {
+        for (index, row) in self.rows.iter().enumerate() {
+            let transfers = matches!(
+                row.mnemonic.as_deref().map(flow),
+                Some(Flow::Jump | Flow::Branch | Flow::Call)
+            );
+            if transfers && !row.inactive && !row.has_error() && row.target.is_none() {
+                let label = row.operands.last().cloned().unwrap_or_default();
+                self.report("L001", index, format!("undefined label `{}`", label));
+            }
+        }
+    }
+
+    fn labels(&mut self, case: LabelCase) {
+        let key = |name: &str| match case {
+            LabelCase::Sensitive => name.to_string(),
+            LabelCase::Insensitive => name.to_ascii_uppercase(),
+        };
+        let mut words = HashSet::new();
+        for row in self.rows.iter().filter(|row| !row.inactive) {
+            let identifiers = row
+                .statement
+                .split(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '?')));
+            words.extend(identifiers.filter(|word| !word.is_empty()).map(key));
+        }
+        let entries = VECTORS.map(|(vector, _)| vector);
+        let mut defined = HashMap::new();
+        for (index, row) in self.rows.iter().enumerate().filter(|(_, row)| !row.inactive) {
+            let Some(label) = &row.label else {
+                continue;
+            };
+            if let Some(first) = defined.insert(key(label), row.line) {
+                let message = format!("label `{}` is already defined on line {}", label, first);
+                self.report("L003", index, message);
+            }
+            let entered = row.space == Space::Code && (row.address == 0 || entries.contains(&row.address));
+            if !words.contains(&key(label)) && !entered {
+                self.report("L002", index, format!("label `{}` is never used", label));
+            }
+        }
+    }
+
+    fn unreachable_code(&mut self) {
+        for pair in 0..self.cfg.blocks.len().saturating_sub(1) {
+            let (before, block) = (&self.cfg.blocks[pair], &self.cfg.blocks[pair + 1]);
+            let targeted = self.cfg.edges.iter().any(|edge| edge.to == pair + 1);
+            let stops = matches!(before.exit, Flow::Jump | Flow::Return | Flow::Indirect);
+            if before.end == block.start && stops && !targeted && !self.labels.contains_key(&block.start) {
+                let last = *before.rows.last().unwrap();
+                let mnemonic = self.rows[last].mnemonic.clone().unwrap_or_default();
+                self.report("L004", block.rows[0], format!("unreachable code after {}", mnemonic));
+            }
+        }
+    }
+
+    fn falls_off_end(&mut self) {
+        let starts = self.cfg.blocks.iter().map(|block| block.start).collect::<HashSet<_>>();
+        let ends = self
+            .cfg
+            .blocks
+            .iter()
+            .filter(|block| matches!(block.exit, Flow::Next | Flow::Branch | Flow::Call))
+            .filter(|block| !starts.contains(&block.end))
+            .map(|block| *block.rows.last().unwrap())
+            .collect::<Vec<_>>();
+        for row in ends {
+            let message = String::from("execution falls off the end of the program here");
+            self.report("L005", row, message);
+        }
+    }
+
+    /// The blocks of the routine starting at `entry`, without following calls.
+    fn routine(&self, entry: usize) -> Vec<usize> {
+        let Some(start) = self.cfg.blocks.iter().position(|block| block.start == entry) else {
+            return vec![];
+        };
+        let mut res = vec![start];
+        let mut index = 0;
+        while index < res.len() {
+            let from = res[index];
+            for edge in self.cfg.edges.iter().filter(|edge| edge.from == from) {
+                if edge.kind != EdgeKind::Call && !res.contains(&edge.to) {
+                    res.push(edge.to);
+                }
+            }
+            index += 1;
+        }
+        res
+    }
+
+    fn ret_in_isr(&mut self) {
+        for (vector, name) in VECTORS {
+            for block in self.routine(vector) {
+                let last = *self.cfg.blocks[block].rows.last().unwrap();
+                if self.rows[last].mnemonic.as_deref() == Some("RET") {
+                    let message = format!("the {} interrupt routine returns with RET, use RETI", name);
+                    self.report("L006", last, message);
+                }
+            }
+        }
+    }
+
+    /// The label of the routine at `entry`, whose first block is `start`. An interrupt
+    /// vector holding only a jump is named after the jump's target.
+    fn routine_name(&self, entry: usize, start: usize) -> String {
+        let block = &self.cfg.blocks[start];
+        let jump = match (block.exit, &block.rows[..]) {
+            (Flow::Jump, [row]) => self.rows[*row].target,
+            _ => None,
+        };
+        let vector = VECTORS.iter().find(|(vector, _)| *vector == entry);
+        match (self.labels.get(&entry), jump.and_then(|target| self.labels.get(&target)), vector) {
+            (Some(label), _, _) | (None, Some(label), _) => label.to_string(),
+            (None, None, Some((_, name))) => format!("{} interrupt", name),
+            (None, None, None) => format!("{:04X}H", entry),
+        }
+    }
+
+    fn unbalanced_stack(&mut self) {
+        let mut entries = self
+            .cfg
+            .edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Call)
+            .map(|edge| self.cfg.blocks[edge.to].start)
+            .collect::<Vec<_>>();
+        entries.extend(VECTORS.map(|(vector, _)| vector));
+        entries.sort();
+        entries.dedup();
+        for entry in entries {
+            let blocks = self.routine(entry);
+            let Some(&start) = blocks.first() else {
+                continue;
+            };
+            let name = self.routine_name(entry, start);
+            let mut depths = HashMap::from([(start, 0i64)]);
+            let mut work = vec![start];
+            let mut returns = vec![];
+            while let Some(block) = work.pop() {
+                let mut depth = depths[&block];
+                for &index in &self.cfg.blocks[block].rows {
+                    match self.rows[index].mnemonic.as_deref() {
+                        Some("PUSH") => depth += 1,
+                        Some("POP") => depth -= 1,
+                        Some("RET" | "RETI") if depth != 0 => returns.push((index, depth)),
+                        _ => {}
+                    }
+                }
+                for edge in self.cfg.edges.iter().filter(|edge| edge.from == block) {
+                    if edge.kind != EdgeKind::Call && !depths.contains_key(&edge.to) {
+                        depths.insert(edge.to, depth);
+                        work.push(edge.to);
+                    }
+                }
+            }
+            for (index, depth) in returns {
+                let message = match depth {
+                    depth if depth > 0 => format!("`{}` returns with {} more PUSH than POP", name, depth),
+                    depth => format!("`{}` returns with {} more POP than PUSH", name, -depth),
+                };
+                self.report("L007", index, message);
+            }
+        }
+    }
+
+    fn register_bank(&mut self) {
+        // Bank selected by a PSW write, with the line of that write.
+        let mut bank: Option<(u8, usize)> = None;
+        for (index, row) in self.rows.iter().enumerate() {
+            let Some(encoding) = row.encoding.as_deref().filter(|_| !row.inactive && row.mnemonic.is_some()) else {
+                continue;
+            };
+            let current = bank.map_or(0, |(bank, _)| bank);
+            let selected = match encoding {
+                [0xD2, bit @ (0xD3 | 0xD4)] => Some(current | 1 << (bit - 0xD3)),
+                [0xC2, bit @ (0xD3 | 0xD4)] => Some(current & !(1 << (bit - 0xD3))),
+                [0x75, 0xD0, value] => Some(value >> 3 & 3),
+                [0x43, 0xD0, value] => Some(current | (value >> 3 & 3)),
+                [0x53, 0xD0, value] => Some(current & (value >> 3 & 3)),
+                [0x63, 0xD0, value] => Some(current ^ (value >> 3 & 3)),
+                // Restores or computes PSW, so the bank is unknown again.
+                [0xD0, 0xD0] | [0xF5, 0xD0] | [0x85, _, 0xD0] => Some(0),
+                _ => None,
+            };
+            if let Some(selected) = selected {
+                bank = (selected != 0).then_some((selected, row.line));
+                continue;
+            }
+            let writes = matches!(encoding[0] & 0xF8, 0x78 | 0xA8 | 0xF8 | 0x08 | 0x18 | 0xD8 | 0xC8);
+            if let Some((selected, line)) = bank.filter(|_| writes) {
+                let register = encoding[0] & 7;
+                let message = format!(
+                    "R{} is in register bank {} at {:02X}H since line {}",
+                    register,
+                    selected,
+                    selected * 8 + register,
+                    line
+                );
+                self.report("L008", index, message);
+            }
+            if matches!(row.mnemonic.as_deref(), Some("RET" | "RETI")) {
+                bank = None;
+            }
+        }
+    }
+}
+
+/// Runs the `enabled` rules over the analyzed rows, in rule order.
+pub fn lint(rows: &[Row], case: LabelCase, enabled: &[&str]) -> Vec<Finding> {
+    let mut labels = HashMap::new();
+    for row in rows.iter().filter(|row| row.space == Space::Code && !row.inactive) {
+        if let Some(label) = &row.label {
+            labels.entry(row.address).or_insert(label.as_str());
+        }
+    }
+    let mut linter = Linter {
+        rows,
+        cfg: Cfg::of(rows),
+        labels,
+        res: vec![],
+    };
+    let on = |id: &str| enabled.contains(&id);
+    if on("L001") {
+        linter.undefined_labels();
+    }
+    if on("L002") || on("L003") {
+        linter.labels(case);
+    }
+    if on("L004") {
+        linter.unreachable_code();
+    }
+    if on("L005") {
+        linter.falls_off_end();
+    }
+    if on("L006") {
+        linter.ret_in_isr();
+    }
+    if on("L007") {
+        linter.unbalanced_stack();
+    }
+    if on("L008") {
+        linter.register_bank();
+    }
+    let mut res = linter.res;
+    res.retain(|finding| on(finding.rule));
+    res.sort_by_key(|finding| finding.line);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn rules() {
+        let source = "ORG 0\nAJMP MAIN\nORG 0BH\nAJMP TICK\nORG 30H\nMAIN: ACALL SAVE\nACALL MISSING\nSJMP MAIN\nNOP\nSAVE: PUSH ACC\nRET\nTICK: SETB RS0\nMOV R0, #1\nRET\nSPARE: NOP\nMAIN: INC A";
+        let rows = analyze(source, &AnalysisOptions::default());
+        let all = parse_rules("all").unwrap();
+        let findings = lint(&rows, LabelCase::Insensitive, &all)
+            .into_iter()
+            .map(|finding| (finding.rule, finding.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("L001", 7),
+                ("L004", 9),
+                ("L007", 11),
+                ("L008", 13),
+                ("L006", 14),
+                ("L002", 15),
+                ("L003", 16),
+                ("L005", 16),
+            ],
+            findings
+        );
+        let messages = lint(&rows, LabelCase::Insensitive, &["L007"])
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![String::from("`SAVE` returns with 1 more PUSH than POP")], messages);
+        let isr = analyze("ORG 0BH\nAJMP TICK\nORG 30H\nTICK: PUSH ACC\nRETI", &AnalysisOptions::default());
+        let findings = lint(&isr, LabelCase::Insensitive, &["L007"]);
+        assert_eq!("`TICK` returns with 1 more PUSH than POP", findings[0].message);
+        let only = parse_rules("unused-label, L003").unwrap();
+        assert_eq!(2, lint(&rows, LabelCase::Insensitive, &only).len());
+        assert!(parse_rules("L999").is_err());
+    }
+}
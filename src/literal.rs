@@ -0,0 +1,206 @@
+use std::fmt::Display;
+
+/// Which spellings of numeric literals an operand may use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LiteralDialect {
+    /// Intel suffixes: `1FH`, `1010B`, `17O`/`17Q`, `42D`.
+    Intel,
+    /// C prefixes: `0x1F`, `0b1010`, `0o17`.
+    C,
+    /// Motorola prefixes: `$1F`, `%1010`.
+    Motorola,
+    /// Any of the above.
+    #[default]
+    Any,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiteralError(pub String);
+
+impl Display for LiteralError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for LiteralError {}
+
+type LiteralResult = Option<Result<i64, LiteralError>>;
+
+/// Regex fragment matching a literal of at most `bytes` bytes, to be compiled case-insensitively.
+///
+/// Digit counts bound the size like the rest of the operand regexes; leading zeros are free.
+pub fn literal_pattern(dialect: LiteralDialect, bytes: usize) -> String {
+    let hex = bytes * 2;
+    let bin = bytes * 8;
+    let oct = bytes * 3;
+    let dec = if bytes == 1 { 3 } else { 5 };
+    let intel = [
+        format!("0*[0-9][0-9A-F]{{0,{}}}H|0+[A-F][0-9A-F]{{0,{}}}H", hex - 1, hex - 1),
+        format!("0*[01]{{1,{}}}B", bin),
+        format!("0*[0-7]{{1,{}}}[OQ]", oct),
+        format!("0*[0-9]{{1,{}}}D?", dec),
+    ];
+    let c = [
+        format!("0X0*[0-9A-F]{{1,{}}}", hex),
+        format!("0B0*[01]{{1,{}}}", bin),
+        format!("0O0*[0-7]{{1,{}}}", oct),
+        format!("0*[0-9]{{1,{}}}", dec),
+    ];
+    let motorola = [
+        format!("\\$0*[0-9A-F]{{1,{}}}", hex),
+        format!("%0*[01]{{1,{}}}", bin),
+        format!("0*[0-9]{{1,{}}}", dec),
+    ];
+    let mut res = match dialect {
+        LiteralDialect::Intel => intel.to_vec(),
+        LiteralDialect::C => c.to_vec(),
+        LiteralDialect::Motorola => motorola.to_vec(),
+        LiteralDialect::Any => c.into_iter().chain(motorola).chain(intel).collect(),
+    };
+    res.push(format!("'[^']{{1,{}}}'", bytes));
+    format!("(?:{})", res.join("|"))
+}
+
+fn radix(digits: &str, radix: u32, text: &str) -> Result<i64, LiteralError> {
+    if digits.is_empty() {
+        return Err(LiteralError(format!("`{}` has no digits", text)));
+    }
+    i64::from_str_radix(digits, radix)
+        .map_err(|_| LiteralError(format!("`{}` is not a valid base {} number", text, radix)))
+}
+
+fn parse_char(text: &str) -> LiteralResult {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    if inner.is_empty() || inner.len() > 2 || !inner.is_ascii() {
+        return Some(Err(LiteralError(format!(
+            "character literal {} must hold one or two ASCII characters",
+            text
+        ))));
+    }
+    Some(Ok(inner.bytes().fold(0, |acc, byte| acc << 8 | byte as i64)))
+}
+
+fn parse_intel(text: &str) -> LiteralResult {
+    if !text.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let (digits, base) = match text.chars().last()? {
+        'H' => (&text[..text.len() - 1], 16),
+        'B' => (&text[..text.len() - 1], 2),
+        'O' | 'Q' => (&text[..text.len() - 1], 8),
+        'D' => (&text[..text.len() - 1], 10),
+        _ => (text, 10),
+    };
+    Some(radix(digits, base, text))
+}
+
+fn parse_c(text: &str) -> LiteralResult {
+    let (digits, base) = if let Some(digits) = text.strip_prefix("0X") {
+        (digits, 16)
+    } else if let Some(digits) = text.strip_prefix("0B").filter(|digits| !digits.is_empty()) {
+        (digits, 2)
+    } else if let Some(digits) = text.strip_prefix("0O") {
+        (digits, 8)
+    } else if text.chars().all(|c| c.is_ascii_digit()) {
+        (text, 10)
+    } else {
+        return None;
+    };
+    // `0B1H` and friends are Intel literals that happen to start like a C prefix.
+    match radix(digits, base, text) {
+        Err(_) if base != 10 && parse_intel(text).is_some_and(|res| res.is_ok()) => None,
+        res => Some(res),
+    }
+}
+
+fn parse_motorola(text: &str) -> LiteralResult {
+    if let Some(digits) = text.strip_prefix('$') {
+        Some(radix(digits, 16, text))
+    } else if let Some(digits) = text.strip_prefix('%') {
+        Some(radix(digits, 2, text))
+    } else if text.chars().all(|c| c.is_ascii_digit()) {
+        Some(radix(text, 10, text))
+    } else {
+        None
+    }
+}
+
+/// Explains why `text` is not a literal when it looks like a mistyped one.
+pub fn diagnose(text: &str) -> Option<String> {
+    let upper = text.trim_start_matches('#').to_ascii_uppercase();
+    let digits = upper.strip_suffix('H')?;
+    (!digits.is_empty()
+        && digits.starts_with(|c: char| c.is_ascii_alphabetic())
+        && digits.chars().all(|c| c.is_ascii_hexdigit()))
+    .then(|| format!("hex literals must start with a digit, write `0{}`", upper))
+}
+
+/// Parses a numeric or character literal written in `dialect`, with an optional leading `-`.
+pub fn parse_literal(text: &str, dialect: LiteralDialect) -> Result<i64, LiteralError> {
+    let (sign, body) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text),
+    };
+    if let Some(res) = parse_char(body) {
+        return res.map(|value| sign * value);
+    }
+    let upper = body.to_ascii_uppercase();
+    let parsers: &[fn(&str) -> LiteralResult] = match dialect {
+        LiteralDialect::Intel => &[parse_intel],
+        LiteralDialect::C => &[parse_c],
+        LiteralDialect::Motorola => &[parse_motorola],
+        LiteralDialect::Any => &[parse_c, parse_motorola, parse_intel],
+    };
+    for parser in parsers {
+        if let Some(res) = parser(&upper) {
+            return res.map(|value| sign * value);
+        }
+    }
+    Err(LiteralError(
+        diagnose(body).unwrap_or_else(|| format!("`{}` is not a number", text)),
+    ))
+}
+
+/// Parses a literal in any dialect, for values whose syntax was already checked.
+pub fn parse_number(text: &str) -> Option<i64> {
+    parse_literal(text, LiteralDialect::Any).ok()
+}
+
+/// Formats `value` as an Intel hex literal of at least `digits` digits, with the leading
+/// zero a value starting with a letter needs.
+pub fn intel_hex(value: usize, digits: usize) -> String {
+    let hex = format!("{:0digits$X}H", value, digits = digits);
+    match hex.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        true => format!("0{}", hex),
+        false => hex,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        for text in ["0x1F", "$1f", "1FH", "0b11111", "11111B", "37O", "37q", "31", "31D"] {
+            assert_eq!(Ok(31), parse_literal(text, LiteralDialect::Any), "{}", text);
+        }
+        assert_eq!(Ok(0x0B), parse_literal("0BH", LiteralDialect::Any));
+        assert_eq!(Ok(65), parse_literal("'A'", LiteralDialect::Intel));
+        assert_eq!(Ok(-3), parse_literal("-3", LiteralDialect::C));
+        assert!(parse_literal("0x1F", LiteralDialect::Intel).is_err());
+    }
+
+    #[test]
+    fn leading_letter() {
+        assert_eq!(
+            Err(LiteralError(String::from(
+                "hex literals must start with a digit, write `0FFH`"
+            ))),
+            parse_literal("FFH", LiteralDialect::Intel)
+        );
+        assert_eq!("0FDH", intel_hex(0xFD, 2));
+        assert_eq!("20H", intel_hex(0x20, 2));
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use crate::{include::SourceLine, report::split_statement};
+
+/// Invocations may nest, but a macro that invokes itself must stop somewhere.
+const MAX_DEPTH: usize = 16;
+
+struct Macro {
+    params: Vec<String>,
+    locals: Vec<String>,
+    body: Vec<String>,
+}
+
+/// Splits a parameter or argument list at commas outside of quotes.
+fn split_args(args: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut start = 0;
+    let mut quoted = None;
+    for (index, c) in args.char_indices() {
+        match c {
+            '\'' | '"' if quoted.is_none() => quoted = Some(c),
+            c if quoted == Some(c) => quoted = None,
+            ',' if quoted.is_none() => {
+                res.push(args[start..index].trim().to_string());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(args[start..].trim().to_string());
+    res.retain(|arg| !arg.is_empty());
+    res
+}
+
+/// Returns the name and parameters of `NAME MACRO a, b` (A51, ASEM-51) or
+/// `.macro NAME a, b` (SDCC).
+fn definition(statement: &str) -> Option<(String, Vec<String>)> {
+    let mut tokens = statement.splitn(3, char::is_whitespace);
+    let first = tokens.next()?;
+    let second = tokens.next().unwrap_or("");
+    let rest = tokens.next().unwrap_or("");
+    if first.eq_ignore_ascii_case(".MACRO") && !second.is_empty() {
+        return Some((second.to_ascii_uppercase(), split_args(rest)));
+    }
+    second
+        .eq_ignore_ascii_case("MACRO")
+        .then(|| (first.to_ascii_uppercase(), split_args(rest)))
+}
+
+fn is_end(statement: &str) -> bool {
+    statement.eq_ignore_ascii_case("ENDM") || statement.eq_ignore_ascii_case(".ENDM")
+}
+
+fn local_names(statement: &str) -> Option<Vec<String>> {
+    let (directive, names) = statement.split_once(char::is_whitespace)?;
+    (directive.eq_ignore_ascii_case("LOCAL") || directive.eq_ignore_ascii_case(".LOCAL"))
+        .then(|| split_args(names))
+}
+
+/// Replaces every identifier of `text` found in `values`, ignoring case.
+fn substitute(text: &str, values: &HashMap<String, String>) -> String {
+    let mut res = String::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, res: &mut String| {
+        match values.get(&word.to_ascii_uppercase()) {
+            Some(value) => res.push_str(value),
+            None => res.push_str(word),
+        }
+        word.clear();
+    };
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '?') {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut res);
+            res.push(c);
+        }
+    }
+    flush(&mut word, &mut res);
+    res
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, Macro>,
+    /// Numbers the `LOCAL` labels of each expansion, like A51's `??0000`.
+    expansions: usize,
+    res: Vec<SourceLine>,
+}
+
+impl Expander {
+    fn expand(&mut self, lines: &[SourceLine], macro_depth: usize) {
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            index += 1;
+            let (_, statement, _) = split_statement(&line.text);
+            if let Some((name, params)) = definition(statement) {
+                let mut res = vec![SourceLine {
+                    skipped: true,
+                    ..line.clone()
+                }];
+                let mut body = vec![];
+                let mut locals = vec![];
+                let mut nested = 0;
+                let mut closed = false;
+                while index < lines.len() {
+                    let line = &lines[index];
+                    index += 1;
+                    res.push(SourceLine {
+                        skipped: true,
+                        ..line.clone()
+                    });
+                    let (_, statement, _) = split_statement(&line.text);
+                    if is_end(statement) {
+                        if nested == 0 {
+                            closed = true;
+                            break;
+                        }
+                        nested -= 1;
+                    } else if definition(statement).is_some() {
+                        nested += 1;
+                    }
+                    match local_names(statement) {
+                        Some(names) if body.is_empty() => locals.extend(names),
+                        _ => body.push(line.text.clone()),
+                    }
+                }
+                if !closed {
+                    res[0].problem = Some(format!("macro `{}` has no ENDM", name));
+                }
+                self.res.extend(res);
+                self.macros.insert(name, Macro { params, locals, body });
+                continue;
+            }
+            let (name, args) = statement
+                .split_once(char::is_whitespace)
+                .unwrap_or((statement, ""));
+            let Some(definition) = self.macros.get(&name.to_ascii_uppercase()) else {
+                self.res.push(line.clone());
+                continue;
+            };
+            let mut invocation = SourceLine {
+                invocation: true,
+                ..line.clone()
+            };
+            if macro_depth >= MAX_DEPTH {
+                invocation.problem = Some(format!("macro `{}` is nested too deeply", name));
+                self.res.push(invocation);
+                continue;
+            }
+            let mut values = HashMap::new();
+            let mut args = split_args(args).into_iter();
+            for param in &definition.params {
+                values.insert(param.to_ascii_uppercase(), args.next().unwrap_or_default());
+            }
+            for local in &definition.locals {
+                values.insert(
+                    local.to_ascii_uppercase(),
+                    format!("??{:04}", self.expansions),
+                );
+            }
+            self.expansions += 1;
+            let body = definition
+                .body
+                .iter()
+                .map(|text| SourceLine {
+                    text: substitute(text.trim(), &values),
+                    macro_depth: macro_depth + 1,
+                    problem: None,
+                    ..line.clone()
+                })
+                .collect::<Vec<_>>();
+            self.res.push(invocation);
+            self.expand(&body, macro_depth + 1);
+        }
+    }
+}
+
+/// Expands macro invocations, keeping each invocation line followed by its expansion.
+///
+/// Definitions stay in the source as skipped lines. Expanded lines carry the file and line
+/// of the invocation so diagnostics point at the call site.
+pub fn expand_macros(lines: &[SourceLine]) -> Vec<SourceLine> {
+    let mut expander = Expander::default();
+    expander.expand(lines, 0);
+    expander.res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion() {
+        let source = "SAVE MACRO REG, COUNT\nLOCAL AGAIN\nAGAIN: PUSH REG\nDJNZ COUNT, AGAIN\nENDM\nSTART: SAVE ACC, R7\nSAVE B, R6";
+        let lines = expand_macros(&SourceLine::plain(source));
+        let texts = lines
+            .iter()
+            .filter(|line| !line.skipped)
+            .map(|line| (line.text.as_str(), line.line, line.macro_depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("START: SAVE ACC, R7", 6, 0),
+                ("??0000: PUSH ACC", 6, 1),
+                ("DJNZ R7, ??0000", 6, 1),
+                ("SAVE B, R6", 7, 0),
+                ("??0001: PUSH B", 7, 1),
+                ("DJNZ R6, ??0001", 7, 1),
+            ],
+            texts
+        );
+        assert_eq!(5, lines.iter().filter(|line| line.skipped).count());
+        assert!(lines[5].invocation);
+    }
+}
@@ -1,158 +1,456 @@
+pub mod batch;
+pub mod cfg;
+pub mod condition;
+pub mod delay;
+pub mod dialect;
+pub mod disasm;
+pub mod encoding;
+pub mod explain;
+pub mod include;
 pub mod instruction;
+pub mod literal;
+pub mod lint;
+pub mod macros;
 pub mod matching;
+pub mod memory_map;
+pub mod output;
 pub mod parser;
+pub mod peephole;
+pub mod report;
+pub mod sdcc;
+pub mod segment;
+pub mod stack;
+pub mod symbols;
+pub mod timer;
+pub mod wcet;
 use std::{
     collections::HashMap,
-    fs,
-    io::{stdin, stdout, Read, Write},
-    path::PathBuf,
+    fs::{self, File},
+    io::{self, stdin, stdout, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use clap::{arg, Command};
+use clap::{arg, ArgGroup, ArgMatches, Command};
+use dialect::Dialect;
 use instruction::AddressingMode;
 use matching::{MatchError, Matcher};
+use output::TableOptions;
+use report::{AnalysisOptions, RoutineMode};
+use literal::LiteralDialect;
+use symbols::LabelCase;
 use parser::{is_valid, ParseError};
 use regex::Regex;
 
-use crate::{
-    instruction::get_addr_mode_map,
-    matching::make_matcher,
-    parser::{get_all_inst_variants, get_regex, get_skip_list},
-};
-
 fn cli() -> Command {
     Command::new("asm2table") 
         .about("Printing the addressing mode, machine cycle and memory bytes line-by-line used in the assembly file")
-        .arg(arg!(<INPUT_FILE> "The asm file to convert").value_parser(clap::value_parser!(PathBuf)))
-        .arg(arg!(-o --output <OUTPUT_FILE> "The csv file to output to").value_parser(clap::value_parser!(PathBuf)))
+        .arg(arg!(<INPUT_FILE>... "The asm files or directories to convert, Intel HEX or binary images to disassemble, `-` for stdin").value_parser(clap::value_parser!(PathBuf)))
+        .arg(arg!(-o --output <OUTPUT_FILE> "The file to output to, `-` for stdout, or a directory when converting several files").value_parser(clap::value_parser!(PathBuf)))
+        .arg(arg!(-f --format <FORMAT> "The output format, csv when an output file is given, dot for the control-flow graph").value_parser(["table", "csv", "latex", "dot"]))
+        .arg(arg!(--address "Include the address column in LaTeX output"))
+        .arg(arg!(--encoding "Include the machine code column in LaTeX output"))
+        .arg(arg!(--totals "Include a totals row in LaTeX output"))
+        .arg(arg!(--columns <COLUMNS> "Comma separated CSV columns: file, line, space, address, label, instruction, modes, bytes, cycles, time, encoding, comment, error").value_parser(output::parse_columns))
+        .arg(arg!(-d --delimiter <CHAR> "The CSV delimiter, `\\t` for TSV").value_parser(output::parse_delimiter))
+        .arg(arg!(--explain "Explain for every line which instruction variants were tried and why they failed"))
+        .arg(arg!(--routines [MODE] "Add per-routine subtotals, starting routines at every label, only at call targets, or per C function or C line of SDCC output").value_parser(["labels", "calls", "c-functions", "c-lines"]).default_missing_value("labels"))
+        .arg(arg!(--"label-case" <POLICY> "Whether user labels and symbols are case sensitive").value_parser(["sensitive", "insensitive"]).default_value("insensitive"))
+        .arg(arg!(--dialect <ASSEMBLER> "The assembler the source is written for: a51 (Keil), asem51 or sdcc (sdas8051)").value_parser(["a51", "asem51", "sdcc"]))
+        .arg(arg!(--literals <DIALECT> "Accepted number literals: intel (1FH), c (0x1F), motorola ($1F) or any, by default those of --dialect or any").value_parser(["intel", "c", "motorola", "any"]))
+        .arg(arg!(-I --include <DIR> "A directory searched for include files, after the including file's own").value_parser(clap::value_parser!(PathBuf)).action(clap::ArgAction::Append))
+        .arg(arg!(-D --define <DEFINITION> "Define a symbol for conditional assembly, as NAME=VALUE or NAME for 1").value_parser(condition::parse_define).action(clap::ArgAction::Append))
+        .arg(arg!(--"collapse-includes" "Fold the lines of included files into their include line"))
+        .arg(arg!(--"memory-map" "Add a memory map of the occupied and free code and the allocated RAM"))
+        .arg(arg!(--device <NAME> "The 8051 derivative whose flash and RAM sizes the program must fit, e.g. 89C51").value_parser(memory_map::parse_device))
+        .arg(arg!(--stack "Add the call graph and the worst-case stack depth of every entry point"))
+        .arg(arg!(--sp <VALUE> "The initial stack pointer, by default from the first `MOV SP, #value` or 07H").value_parser(stack::parse_sp))
+        .arg(arg!(--wcet "Add the worst-case execution time of every subroutine and interrupt routine"))
+        .arg(arg!(--"loop-bound" <BOUND> "The most iterations of the loop starting at a label, as LABEL=N").value_parser(wcet::parse_loop_bound).action(clap::ArgAction::Append))
+        .arg(arg!(--lint [RULES] "Report common mistakes as warnings, for all rules or the comma separated rule IDs or names").value_parser(lint::parse_rules).default_missing_value("all"))
+        .arg(arg!(--allow <RULES> "Disable lint rules, by comma separated rule IDs or names").value_parser(lint::parse_rules).action(clap::ArgAction::Append))
+        .arg(arg!(--suggest "Add peephole optimization suggestions with their byte and cycle savings"))
+        .arg(arg!(--rewrite <FILE> "Write the source with the suggestions applied, or a directory when converting several files").value_parser(clap::value_parser!(PathBuf)))
+        .arg(arg!(--clock <FREQUENCY> "The oscillator frequency, e.g. 11.0592MHz").value_parser(report::parse_clock))
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("delay")
+                .about("Generate a delay subroutine of nested DJNZ loops and print it as a table")
+                .arg(arg!(<DELAY> "The delay including the LCALL, e.g. 10ms, 500us or 1.5s").value_parser(delay::parse_duration))
+                .arg(arg!(--clock <FREQUENCY> "The oscillator frequency, e.g. 11.0592MHz").value_parser(report::parse_clock).default_value("12MHz"))
+                .arg(arg!(--name <LABEL> "The label of the subroutine").default_value("DELAY"))
+                .arg(arg!(-f --format <FORMAT> "The output format").value_parser(["table", "csv", "latex"]).default_value("table"))
+                .arg(arg!(--source "Print the assembly source instead of the table")),
+        )
+        .subcommand(
+            Command::new("timer")
+                .about("Calculate timer reload values for a baud rate or an overflow period")
+                .arg(arg!(--baud <RATE> "The UART baud rate, clocked by timer 1").value_parser(timer::parse_baud))
+                .arg(arg!(--period <DURATION> "The time between overflows, e.g. 10ms").value_parser(delay::parse_duration))
+                .group(ArgGroup::new("goal").args(["baud", "period"]).required(true))
+                .arg(arg!(--clock <FREQUENCY> "The oscillator frequency, e.g. 11.0592MHz").value_parser(report::parse_clock).default_value("12MHz"))
+                .arg(arg!(--timer <TIMER> "The timer, 1 for a baud rate and 0 otherwise by default").value_parser(clap::value_parser!(u8).range(0..=1)))
+                .arg(arg!(--mode <MODE> "The timer mode, 2 for a baud rate and 1 otherwise by default").value_parser(clap::value_parser!(u8).range(0..=3)))
+                .arg(arg!(--smod "Double the baud rate with SMOD in PCON"))
+                .arg(arg!(--init "Print the instructions setting the timer up")),
+        )
 }
 
-fn main() {
-    let matches = cli().get_matches();
+/// Reads the source from `path`, or from stdin when `path` is `-`. Intel HEX (`.hex`,
+/// `.ihx`) and binary (`.bin`) images are disassembled into source.
+fn read_input(path: &Path) -> io::Result<String> {
+    if path == Path::new("-") {
+        let mut contents = String::new();
+        stdin().read_to_string(&mut contents)?;
+        return Ok(contents);
+    }
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("hex" | "ihx") => disasm::parse_hex(&fs::read_to_string(path)?)
+            .map(|image| disasm::disassemble(&image))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Some("bin") => Ok(disasm::disassemble(&disasm::image_of_binary(&fs::read(path)?))),
+        _ => fs::read_to_string(path),
+    }
+}
+
+/// Opens `path` for writing, or stdout when `path` is `-` or absent.
+fn open_output(path: Option<&PathBuf>) -> io::Result<Box<dyn Write>> {
+    match path {
+        Some(path) if path != Path::new("-") => Ok(Box::new(File::create(path)?)),
+        _ => Ok(Box::new(stdout())),
+    }
+}
+
+/// Writes `file` with the peephole suggestions applied to `target`.
+fn write_rewritten(
+    file: &Path,
+    rows: &[report::Row],
+    analysis: &AnalysisOptions,
+    target: &Path,
+) -> io::Result<()> {
+    if file == Path::new("-") {
+        return Err(io::Error::other("the standard input cannot be read twice"));
+    }
+    let contents = read_input(file)?;
+    let suggestions = peephole::suggest(rows, analysis);
+    fs::write(target, peephole::rewrite(&contents, rows, &suggestions))
+}
+
+/// Generates the subroutine of the `delay` subcommand and prints it with the cycles it
+/// achieves, after a table or, when that could be read by a tool, to stderr.
+fn run_delay(matches: &ArgMatches) -> ExitCode {
+    let seconds = *matches.get_one::<f64>("DELAY").unwrap();
+    let clock = *matches.get_one::<f64>("clock").unwrap();
+    let name = matches.get_one::<String>("name").unwrap();
+    let format = matches.get_one::<String>("format").unwrap().as_str();
+    let target = (seconds / report::cycle_time(1, clock)).round() as usize;
+    let routine = match delay::Delay::of(name, target) {
+        Ok(routine) => routine,
+        Err(err) => {
+            eprintln!("Could not generate the delay: {}", err);
+            return ExitCode::from(2);
+        }
+    };
 
-    let file = matches.get_one::<PathBuf>("INPUT_FILE").unwrap();
-    println!("{:?}", file);
-    if !file.exists() {
-        eprintln!("File doesn't exist. Please provide a valid file!");
-        return;
-    }
-
-    let contents = fs::read_to_string(file).unwrap();
-    let all_inst_map = get_all_inst_variants();
-    let regex_map = get_regex();
-    let addr_map_mode = get_addr_mode_map();
-    let skip_list = get_skip_list();
-    let matcher = make_matcher();
-    let mut res: Vec<[String; 4]> = vec![];
-    for line in contents.lines().map(String::from) {
-        let mem = get_memory(&line, &all_inst_map, &regex_map, &addr_map_mode, &skip_list);
-        let cycles = get_cycle(&line, &matcher, &all_inst_map, &regex_map, &skip_list);
-        let modes = get_modes(&line, &all_inst_map, &regex_map, &addr_map_mode, &skip_list);
-
-        res.push([
-            if line.contains([';']) {
-                line.split_once(';').unwrap().0.trim().to_string()
-            } else {
-                line.clone()
-            },
-            if modes.is_ok() {
-                modes
-                    .unwrap()
-                    .into_iter()
-                    .map(|mode| format!("{:?}", mode))
-                    .collect::<Vec<String>>()
-                    .join(", ")
-            } else {
-                "".to_string()
-            },
-            if mem.is_ok() {
-                format!("{:?}", mem.clone().unwrap())
-            } else {
-                "-1".to_string()
-            },
-            if cycles.is_ok() {
-                format!("{:?}", cycles.clone().unwrap())
-            } else {
-                "-1".to_string()
-            },
-        ]);
-    }
-
-    if let Some(csv_file) = matches.get_one::<PathBuf>("output") {
-        let mut writer = csv::Writer::from_path(csv_file).expect("File could not be opened!");
-        for line in res {
-            let record = line.iter().map(String::as_bytes);
-            writer
-                .write_record(record)
-                .expect("Could not write to file!");
+    let source = routine.source();
+    let written = match matches.get_flag("source") {
+        true => stdout().write_all(source.as_bytes()),
+        false => {
+            let rows = report::analyze(&source, &AnalysisOptions::default());
+            let options = TableOptions {
+                clock,
+                ..Default::default()
+            };
+            output::write(&rows, format, &options, stdout())
         }
-        return;
+    };
+    if let Err(err) = written {
+        eprintln!("Could not write output: {}", err);
+        return ExitCode::from(2);
     }
+    let error = routine.cycles as i64 - routine.target as i64;
+    let summary = format!(
+        "Target:   {} cycles ({:.3} us at {} MHz)\nAchieved: {} cycles ({:.3} us), counting the LCALL\nError:    {} cycles ({:.3}%)",
+        routine.target,
+        seconds * 1e6,
+        clock / 1e6,
+        routine.cycles,
+        report::cycle_time(routine.cycles, clock) * 1e6,
+        error,
+        error as f64 * 100.0 / routine.target as f64
+    );
+    match format == "table" && !matches.get_flag("source") {
+        true => println!("\n{}", summary),
+        false => eprintln!("{}", summary),
+    }
+    ExitCode::SUCCESS
+}
 
-    let mut max_size: (usize, usize, usize, usize) = (
-        "Instruction".len(),
-        "Modes".len(),
-        "Cycles".len(),
-        "Memory".len(),
+/// Calculates the reload values of the `timer` subcommand and prints them with the rate
+/// they achieve.
+fn run_timer(matches: &ArgMatches) -> ExitCode {
+    let clock = *matches.get_one::<f64>("clock").unwrap();
+    let goal = match matches.get_one::<f64>("baud") {
+        Some(baud) => timer::Goal::Baud(*baud),
+        None => timer::Goal::Period(*matches.get_one::<f64>("period").unwrap()),
+    };
+    let baud = matches!(goal, timer::Goal::Baud(_));
+    let number = matches.get_one::<u8>("timer").map_or(baud as usize, |&timer| timer as usize);
+    let mode = matches.get_one::<u8>("mode").map_or(if baud { 2 } else { 1 }, |&mode| mode as usize);
+    let setting = match (baud, number) {
+        (true, 0) => Err(String::from("only timer 1 clocks the serial port")),
+        _ => timer::Setting::of(clock, number, mode, matches.get_flag("smod"), goal),
+    };
+    let setting = match setting {
+        Ok(setting) => setting,
+        Err(err) => {
+            eprintln!("Could not calculate the timer: {}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let modes = ["13-bit", "16-bit", "8-bit auto-reload", "split 8-bit"];
+    println!(
+        "Timer {}, mode {} ({}), SMOD={}",
+        setting.timer, setting.mode, modes[setting.mode], setting.smod as u8
     );
+    println!("Overflow every {} machine cycles", setting.ticks);
+    for (register, value) in setting.registers() {
+        println!("{:<9} {} ({})", format!("{}:", register), literal::intel_hex(value, 2), value);
+    }
+    match goal {
+        timer::Goal::Baud(target) => println!(
+            "Baud rate: {:.2} for {}, error {:+.3}%",
+            setting.achieved,
+            target,
+            setting.error()
+        ),
+        timer::Goal::Period(target) => println!(
+            "Period:    {:.3} us for {:.3} us, error {:+.3}%",
+            setting.achieved * 1e6,
+            target * 1e6,
+            setting.error()
+        ),
+    }
+    if matches.get_flag("init") {
+        print!("\n{}", setting.init_code());
+    }
+    ExitCode::SUCCESS
+}
 
-    for line in &res {
-        let to_size = |tuple: &[String; 4]| {
-            (
-                tuple[0].len(),
-                tuple[1].len(),
-                tuple[2].len(),
-                tuple[3].len(),
-            )
-        };
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+    match matches.subcommand() {
+        Some(("delay", matches)) => return run_delay(matches),
+        Some(("timer", matches)) => return run_timer(matches),
+        _ => {}
+    }
 
-        let sizes = to_size(line);
+    let inputs = matches
+        .get_many::<PathBuf>("INPUT_FILE")
+        .unwrap()
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Some(file) = inputs
+        .iter()
+        .find(|file| *file != Path::new("-") && !file.exists())
+    {
+        eprintln!("File {} doesn't exist. Please provide a valid file!", file.display());
+        return ExitCode::from(2);
+    }
+    let files = match batch::collect_inputs(&inputs) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("Could not list the input files: {}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let output_file = matches.get_one::<PathBuf>("output");
+    let format = match matches.get_one::<String>("format") {
+        _ if matches.get_flag("explain") => "explain",
+        Some(format) => format.as_str(),
+        None if output_file.is_some() => "csv",
+        None => "table",
+    };
+    let mut options = TableOptions {
+        address: matches.get_flag("address"),
+        encoding: matches.get_flag("encoding"),
+        totals: matches.get_flag("totals"),
+        ..Default::default()
+    };
+    if let Some(columns) = matches.get_one::<Vec<output::Column>>("columns") {
+        options.columns = columns.clone();
+    }
+    if let Some(delimiter) = matches.get_one::<u8>("delimiter") {
+        options.delimiter = *delimiter;
+    }
+    if let Some(clock) = matches.get_one::<f64>("clock") {
+        options.clock = *clock;
+    }
+    options.memory_map = matches.get_flag("memory-map");
+    options.device = matches.get_one::<memory_map::Device>("device").copied();
+    options.stack = matches.get_flag("stack");
+    options.initial_sp = matches.get_one::<usize>("sp").copied();
+    options.wcet = matches.get_flag("wcet");
+    options.suggest = matches.get_flag("suggest");
+    options.loop_bounds = matches
+        .get_many::<(String, usize)>("loop-bound")
+        .map(|bounds| bounds.cloned().collect())
+        .unwrap_or_default();
+    options.routines = match matches.get_one::<String>("routines").map(String::as_str) {
+        Some("calls") => Some(RoutineMode::Calls),
+        Some("c-functions") => Some(RoutineMode::CFunctions),
+        Some("c-lines") => Some(RoutineMode::CLines),
+        Some(_) => Some(RoutineMode::Labels),
+        None => None,
+    };
 
-        if max_size.0 < sizes.0 {
-            max_size.0 = sizes.0
+    let dialect = matches
+        .get_one::<String>("dialect")
+        .and_then(|name| Dialect::from_name(name));
+    let analysis = AnalysisOptions {
+        dialect: dialect.unwrap_or_default(),
+        label_case: match matches.get_one::<String>("label-case").map(String::as_str) {
+            Some("sensitive") => LabelCase::Sensitive,
+            _ => LabelCase::Insensitive,
+        },
+        literals: match matches.get_one::<String>("literals").map(String::as_str) {
+            Some("intel") => LiteralDialect::Intel,
+            Some("c") => LiteralDialect::C,
+            Some("motorola") => LiteralDialect::Motorola,
+            Some(_) => LiteralDialect::Any,
+            None => dialect.map(|dialect| dialect.literals()).unwrap_or_default(),
+        },
+        include_paths: matches
+            .get_many::<PathBuf>("include")
+            .map(|dirs| dirs.cloned().collect())
+            .unwrap_or_default(),
+        defines: matches
+            .get_many::<(String, i64)>("define")
+            .map(|defines| defines.cloned().collect())
+            .unwrap_or_default(),
+    };
+    options.analysis = analysis.clone();
+    let allowed = matches
+        .get_many::<Vec<&str>>("allow")
+        .map(|rules| rules.flatten().copied().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let lint_rules = matches.get_one::<Vec<&str>>("lint").map(|rules| {
+        let mut rules = rules.clone();
+        rules.retain(|rule| !allowed.contains(rule));
+        rules
+    });
+    let results = batch::analyze_all(&files, &analysis, read_input);
+    let batch_mode = files.len() > 1;
+    let output_dir = output_file.filter(|path| batch_mode && *path != Path::new("-"));
+    let rewrite_path = matches.get_one::<PathBuf>("rewrite");
+    let rewrite_dir = rewrite_path.filter(|_| batch_mode);
+    for dir in output_dir.into_iter().chain(rewrite_dir) {
+        if let Err(err) = fs::create_dir_all(dir) {
+            eprintln!("Could not create {}: {}", dir.display(), err);
+            return ExitCode::from(2);
         }
+    }
+    let extension = match format {
+        "csv" => "csv",
+        "latex" => "tex",
+        "dot" => "dot",
+        _ => "txt",
+    };
 
-        if max_size.1 < sizes.1 {
-            max_size.1 = sizes.1
+    let mut io_failed = false;
+    let mut diagnostics = false;
+    let mut summaries = vec![];
+    let report_names = batch::report_names(&files, Some(extension));
+    let rewrite_names = batch::report_names(&files, None);
+    for (index, (file, rows)) in files.iter().zip(results).enumerate() {
+        let rows = match rows {
+            Ok(rows) if matches.get_flag("collapse-includes") => report::collapse_includes(rows),
+            Ok(rows) => rows,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", file.display(), err);
+                io_failed = true;
+                continue;
+            }
+        };
+        let rows = rows.as_slice();
+        diagnostics |= rows.iter().any(report::Row::has_error);
+        for problem in memory_map::MemoryMap::of(rows, options.device).problems() {
+            eprintln!("{}: error: {}", file.display(), problem);
+            diagnostics = true;
+        }
+        if options.stack && output::stack_report(rows, &options).overflows() {
+            eprintln!("{}: warning: the stack may overflow the internal RAM", file.display());
+            diagnostics = true;
+        }
+        if let Some(rules) = &lint_rules {
+            for finding in lint::lint(rows, analysis.label_case, rules) {
+                eprintln!(
+                    "{}:{}: warning[{}]: {} ({})",
+                    finding.file.as_deref().unwrap_or(file).display(),
+                    finding.line,
+                    finding.rule,
+                    finding.message,
+                    finding.name()
+                );
+                diagnostics = true;
+            }
+        }
+        summaries.push(batch::FileSummary::of(file, rows));
+        if let Some(path) = rewrite_path {
+            let target = match batch_mode {
+                true => path.join(&rewrite_names[index]),
+                false => path.clone(),
+            };
+            if let Err(err) = write_rewritten(file, rows, &analysis, &target) {
+                eprintln!("Could not rewrite {}: {}", file.display(), err);
+                io_failed = true;
+            }
         }
 
-        if max_size.2 < sizes.2 {
-            max_size.2 = sizes.2
+        let written = match output_dir {
+            Some(dir) => open_output(Some(&dir.join(&report_names[index])))
+                .and_then(|writer| output::write(rows, format, &options, writer)),
+            None if batch_mode => writeln!(stdout(), "==> {} <==", file.display())
+                .and_then(|_| output::write(rows, format, &options, stdout()))
+                .and_then(|_| writeln!(stdout())),
+            None => open_output(output_file)
+                .and_then(|writer| output::write(rows, format, &options, writer)),
+        };
+        if let Err(err) = written {
+            eprintln!("Could not write output: {}", err);
+            io_failed = true;
         }
+    }
 
-        if max_size.3 < sizes.3 {
-            max_size.3 = sizes.3
+    if batch_mode && format != "dot" {
+        let written = match output_dir {
+            Some(dir) => open_output(Some(&dir.join("summary").with_extension(extension)))
+                .and_then(|writer| output::write_summary(&summaries, format, &options, writer)),
+            None => output::write_summary(&summaries, format, &options, stdout()),
+        };
+        if let Err(err) = written {
+            eprintln!("Could not write output: {}", err);
+            io_failed = true;
         }
     }
 
-    println!(
-        "{:^len1$}  {:^len2$}  {:^len3$}  {:^len4$}",
-        "Instruction",
-        "Modes",
-        "Cycles",
-        "Memory",
-        len1 = max_size.0,
-        len2 = max_size.1,
-        len3 = max_size.2,
-        len4 = max_size.3
-    );
-    for line in res {
-        println!(
-            "{:<len1$}: {:>len2$}, {:>len3$}, {:>len4$}",
-            line[0],
-            line[1],
-            line[2],
-            line[3],
-            len1 = max_size.0,
-            len2 = max_size.1,
-            len3 = max_size.2,
-            len4 = max_size.3
-        );
+    let interactive = stdin().is_terminal() && stdout().is_terminal();
+    if interactive && output_file.is_none() && format == "table" {
+        print!("Press Enter to quit...\r");
+        stdout().flush().expect("Flush failed");
+        let _ = stdin().read(&mut [0u8]).unwrap();
     }
 
-    print!("Press Enter to quit...\r");
-    stdout().flush().expect("Flush failed");
-    let _ = stdin().read(&mut [0u8]).unwrap();
+    if io_failed {
+        ExitCode::from(2)
+    } else if diagnostics {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
 pub fn get_modes(
@@ -187,9 +485,17 @@ pub fn get_modes(
     if skip_list.iter().any(|reg_pat| reg_pat.is_match(line)) {
         return Ok(vec![]);
     }
-    let all_operands = all_inst.get(instruction).ok_or(ParseError)?;
+    let all_operands = all_inst
+        .get(&instruction.to_ascii_uppercase())
+        .ok_or(ParseError)?;
+    if all_operands.is_empty() {
+        return Ok(vec![]);
+    }
     let operands = raw_operands.split(',').map(str::trim);
     for ops in all_operands {
+        if ops.len() != operands.clone().count() {
+            continue;
+        }
         let mut op_modes = Vec::new();
         let mut is_match = true;
         for (op1, op2) in ops.iter().zip(operands.clone()) {
@@ -238,9 +544,17 @@ pub fn get_memory(
     if skip_list.iter().any(|reg_pat| reg_pat.is_match(line)) {
         return Ok(0);
     }
-    let all_operands = all_inst.get(instruction).ok_or(ParseError)?;
+    let all_operands = all_inst
+        .get(&instruction.to_ascii_uppercase())
+        .ok_or(ParseError)?;
+    if all_operands.is_empty() {
+        return Ok(1);
+    }
     let operands = raw_operands.split(',').map(str::trim);
     for ops in all_operands {
+        if ops.len() != operands.clone().count() {
+            continue;
+        }
         let mut op_modes = Vec::new();
         let mut is_match = true;
         for (op1, op2) in ops.iter().zip(operands.clone()) {
@@ -296,9 +610,23 @@ where
     if skip_list.iter().any(|reg_pat| reg_pat.is_match(line)) {
         return Ok(0);
     }
-    let all_operands = all_inst.get(instruction).ok_or(MatchError)?;
+    let all_operands = all_inst
+        .get(&instruction.to_ascii_uppercase())
+        .ok_or(MatchError)?;
+    if all_operands.is_empty() {
+        if !raw_operands.trim().is_empty() {
+            return Err(MatchError);
+        }
+        return matcher.do_match(instruction::Instruction {
+            name: instruction.to_ascii_uppercase(),
+            operands: vec![],
+        });
+    }
     let operands = raw_operands.split(',').map(str::trim);
     for ops in all_operands {
+        if ops.len() != operands.clone().count() {
+            continue;
+        }
         let mut op_modes = Vec::new();
         let mut is_match = true;
         for (op1, op2) in ops.iter().zip(operands.clone()) {
@@ -310,7 +638,7 @@ where
         }
         if is_match {
             let res = matcher.do_match(instruction::Instruction {
-                name: instruction.to_string(),
+                name: instruction.to_ascii_uppercase(),
                 operands: op_modes,
             });
             return res;
@@ -322,6 +650,11 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        instruction::get_addr_mode_map,
+        matching::make_matcher,
+        parser::{get_all_inst_variants, get_regex, get_skip_list},
+    };
 
     #[test]
     fn sjmp() {
@@ -343,6 +676,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ljmp() {
+        let all_inst_map = get_all_inst_variants();
+        let regex_map = get_regex();
+        let addr_map_mode = get_addr_mode_map();
+        let skip_list = get_skip_list();
+        let matcher = make_matcher();
+        assert!(is_valid("LJMP MAIN", &all_inst_map, &regex_map, &skip_list).is_ok());
+        assert_eq!(
+            Ok(3),
+            get_memory("LJMP 1234H", &all_inst_map, &regex_map, &addr_map_mode, &skip_list)
+        );
+        assert_eq!(
+            2,
+            get_cycle("LJMP MAIN", &matcher, &all_inst_map, &regex_map, &skip_list).unwrap()
+        );
+        assert!(is_valid("LJMP MAIN, A", &all_inst_map, &regex_map, &skip_list).is_err());
+    }
+
     #[test]
     fn setb() {
         let all_inst_map = get_all_inst_variants();
@@ -423,6 +775,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ret() {
+        let all_inst_map = get_all_inst_variants();
+        let regex_map = get_regex();
+        let addr_map_mode = get_addr_mode_map();
+        let skip_list = get_skip_list();
+        let matcher = make_matcher();
+        assert_eq!(
+            Ok(1),
+            get_memory("RET", &all_inst_map, &regex_map, &addr_map_mode, &skip_list)
+        );
+        assert_eq!(
+            2,
+            get_cycle("RET", &matcher, &all_inst_map, &regex_map, &skip_list).unwrap()
+        );
+        assert_eq!(
+            Ok(vec![]),
+            get_modes("RET", &all_inst_map, &regex_map, &addr_map_mode, &skip_list)
+        );
+
+        // An instruction without operands rejects any it is given.
+        assert!(is_valid("RET A", &all_inst_map, &regex_map, &skip_list).is_err());
+        assert!(get_memory("RET A", &all_inst_map, &regex_map, &addr_map_mode, &skip_list).is_err());
+        assert!(get_cycle("RET A", &matcher, &all_inst_map, &regex_map, &skip_list).is_err());
+    }
+
+    #[test]
+    fn operand_count() {
+        let all_inst_map = get_all_inst_variants();
+        let regex_map = get_regex();
+        let addr_map_mode = get_addr_mode_map();
+        let skip_list = get_skip_list();
+        let matcher = make_matcher();
+        for line in ["MOV A, #1, #2", "MOV A", "CJNE A, #1"] {
+            assert!(is_valid(line, &all_inst_map, &regex_map, &skip_list).is_err(), "{}", line);
+            assert!(get_modes(line, &all_inst_map, &regex_map, &addr_map_mode, &skip_list).is_err());
+            assert!(get_memory(line, &all_inst_map, &regex_map, &addr_map_mode, &skip_list).is_err());
+            assert!(get_cycle(line, &matcher, &all_inst_map, &regex_map, &skip_list).is_err());
+        }
+        assert!(is_valid("CJNE A, #1, HERE", &all_inst_map, &regex_map, &skip_list).is_ok());
+    }
+
     #[test]
     fn jnb() {
         let all_inst_map = get_all_inst_variants();
@@ -111,6 +111,8 @@ pub fn make_matcher() -> impl Matcher {
         .specific("MOV", vec!["bit", "C"], 2)
         .specific("ANL", vec!["C", "bit"], 2)
         .specific("ORL", vec!["C", "bit"], 2)
+        .specific("ANL", vec!["C", "/bit"], 2)
+        .specific("ORL", vec!["C", "/bit"], 2)
         .inst("MUL", 4)
         .inst("DIV", 4)
         .inst("MOVC", 2)
@@ -127,6 +129,7 @@ pub fn make_matcher() -> impl Matcher {
         .inst("ACALL", 2)
         .inst("LCALL", 2)
         .inst("AJMP", 2)
+        .inst("LJMP", 2)
         .inst("JMP", 2)
         .inst("SJMP", 2)
         .inst("CJNE", 2)
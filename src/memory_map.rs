@@ -0,0 +1,274 @@
+use crate::{
+    report::{Row, Statistics},
+    segment::Space,
+};
+
+/// Memory sizes of an 8051 derivative.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub name: &'static str,
+    /// On-chip flash or ROM, in bytes.
+    pub code: usize,
+    /// Internal RAM including the indirectly addressable upper half, in bytes.
+    pub ram: usize,
+    /// On-chip external RAM, in bytes.
+    pub xram: usize,
+}
+
+/// Every 8051 has 16 bytes of bit-addressable RAM.
+pub const BIT_SPACE: usize = 128;
+
+pub const DEVICES: [Device; 12] = [
+    Device { name: "8051", code: 4096, ram: 128, xram: 0 },
+    Device { name: "8052", code: 8192, ram: 256, xram: 0 },
+    Device { name: "89C51", code: 4096, ram: 128, xram: 0 },
+    Device { name: "89C52", code: 8192, ram: 256, xram: 0 },
+    Device { name: "89C55", code: 20480, ram: 256, xram: 0 },
+    Device { name: "89S51", code: 4096, ram: 128, xram: 0 },
+    Device { name: "89S52", code: 8192, ram: 256, xram: 0 },
+    Device { name: "89C2051", code: 2048, ram: 128, xram: 0 },
+    Device { name: "89C4051", code: 4096, ram: 128, xram: 0 },
+    Device { name: "AT89C51ED2", code: 65536, ram: 256, xram: 1792 },
+    Device { name: "P89V51RD2", code: 65536, ram: 256, xram: 768 },
+    Device { name: "DS89C450", code: 65536, ram: 256, xram: 1024 },
+];
+
+/// Parses a device name as given to `--device`, ignoring case and an `AT` prefix.
+pub fn parse_device(text: &str) -> Result<Device, String> {
+    let name = text.trim().to_ascii_uppercase();
+    DEVICES
+        .into_iter()
+        .find(|device| device.name == name || format!("AT{}", device.name) == name)
+        .ok_or_else(|| {
+            let names = DEVICES.map(|device| device.name);
+            format!("unknown device, expected one of {}", names.join(", "))
+        })
+}
+
+/// A range of locations, `end` exclusive, named after the label at its start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub name: Option<String>,
+}
+
+impl Region {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Capacity of a space on the selected device and how much of it is used.
+pub struct Capacity {
+    pub space: &'static str,
+    pub unit: &'static str,
+    pub used: usize,
+    pub size: usize,
+}
+
+impl Capacity {
+    pub fn percent(&self) -> f64 {
+        match self.size {
+            0 => 100.0,
+            size => self.used as f64 * 100.0 / size as f64,
+        }
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.used > self.size
+    }
+}
+
+pub struct MemoryMap {
+    /// Occupied code, split at every label and `ORG`.
+    pub code: Vec<Region>,
+    /// Unused code between the occupied ranges, up to the end of the device's flash.
+    pub gaps: Vec<Region>,
+    /// Internal RAM reserved with `DS` or declared with `DATA` and `IDATA`.
+    pub ram: Vec<Region>,
+    /// Bit-addressable RAM reserved with `DBIT` or declared with `BIT`, in bit addresses.
+    pub bits: Vec<Region>,
+    /// Use of each space against the device, empty without a device.
+    pub capacities: Vec<Capacity>,
+    /// The device the capacities are checked against.
+    pub device: Option<Device>,
+}
+
+fn add_region(regions: &mut Vec<Region>, start: usize, length: usize, name: Option<&String>) {
+    match regions.last_mut() {
+        Some(last) if name.is_none() && last.end == start => last.end += length,
+        _ => regions.push(Region {
+            start,
+            end: start + length,
+            name: name.cloned(),
+        }),
+    }
+}
+
+impl MemoryMap {
+    pub fn of(rows: &[Row], device: Option<Device>) -> Self {
+        let mut code = vec![];
+        let mut ram = vec![];
+        let mut bits = vec![];
+        for row in rows.iter().filter(|row| !row.inactive) {
+            let length = row.bytes.clone().unwrap_or(0) + row.data_bytes;
+            match row.space {
+                Space::Code if length > 0 => {
+                    add_region(&mut code, row.address, length, row.label.as_ref())
+                }
+                Space::Data | Space::Idata if length > 0 => {
+                    add_region(&mut ram, row.address, length, row.label.as_ref())
+                }
+                Space::Bit if length > 0 => {
+                    add_region(&mut bits, row.address, length, row.label.as_ref())
+                }
+                _ => {}
+            }
+            let Some(declaration) = &row.declaration else {
+                continue;
+            };
+            let name = Some(&declaration.name);
+            match declaration.space {
+                // Addresses from 80H up are special function registers, not RAM.
+                Space::Data if declaration.address < 0x80 => {
+                    add_region(&mut ram, declaration.address, 1, name)
+                }
+                Space::Idata if declaration.address < 0x100 => {
+                    add_region(&mut ram, declaration.address, 1, name)
+                }
+                Space::Bit if declaration.address < BIT_SPACE => {
+                    add_region(&mut bits, declaration.address, 1, name)
+                }
+                _ => {}
+            }
+        }
+        code.sort_by_key(|region| region.start);
+        ram.sort_by_key(|region| region.start);
+        bits.sort_by_key(|region| region.start);
+
+        let code_end = code.iter().map(|region| region.end).max().unwrap_or(0);
+        let end = device.map_or(code_end, |device| device.code);
+        let mut gaps = vec![];
+        let mut free = 0;
+        for region in code.iter().chain([&Region { start: end, end, name: None }]) {
+            if region.start > free {
+                gaps.push(Region {
+                    start: free,
+                    end: region.start,
+                    name: None,
+                });
+            }
+            free = free.max(region.end);
+        }
+
+        let capacities = match device {
+            Some(device) => {
+                let usage = Statistics::of(rows).usage;
+                let used = |space| usage.get(&space).copied().unwrap_or(0);
+                let total = |regions: &[Region]| regions.iter().map(Region::len).sum();
+                vec![
+                    Capacity {
+                        space: "code",
+                        unit: Space::Code.unit(),
+                        used: used(Space::Code),
+                        size: device.code,
+                    },
+                    Capacity {
+                        space: "internal RAM",
+                        unit: Space::Data.unit(),
+                        used: total(&ram),
+                        size: device.ram,
+                    },
+                    Capacity {
+                        space: "bit space",
+                        unit: Space::Bit.unit(),
+                        used: total(&bits),
+                        size: BIT_SPACE,
+                    },
+                    Capacity {
+                        space: "external RAM",
+                        unit: Space::Xdata.unit(),
+                        used: used(Space::Xdata),
+                        size: device.xram,
+                    },
+                ]
+            }
+            None => vec![],
+        };
+        MemoryMap {
+            code,
+            gaps,
+            ram,
+            bits,
+            capacities,
+            device,
+        }
+    }
+
+    /// Describes every space the program does not fit in.
+    pub fn problems(&self) -> Vec<String> {
+        let mut res = self
+            .capacities
+            .iter()
+            .filter(|capacity| capacity.exceeded())
+            .map(|capacity| {
+                format!(
+                    "{} needs {} {} but the device has {}",
+                    capacity.space, capacity.used, capacity.unit, capacity.size
+                )
+            })
+            .collect::<Vec<_>>();
+        let flash = self.device.map_or(usize::MAX, |device| device.code);
+        if let Some(region) = self.code.iter().find(|region| region.end > flash) {
+            res.push(format!("code at {:04X}H lies beyond the end of the flash", region.start.max(flash)));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn map() {
+        let source = "COUNT DATA 30H\nP1 DATA 90H\nREADY BIT 20H.1\nDSEG AT 40H\nBUFFER: DS 16\nCSEG AT 0\nAJMP MAIN\nORG 30H\nMAIN: INC COUNT\nLOOP: SJMP LOOP";
+        let rows = analyze(source, &AnalysisOptions::default());
+        let map = MemoryMap::of(&rows, Some(parse_device("at89c2051").unwrap()));
+        let region = |start, end, name: Option<&str>| Region {
+            start,
+            end,
+            name: name.map(String::from),
+        };
+        assert_eq!(
+            vec![
+                region(0, 2, None),
+                region(0x30, 0x32, Some("MAIN")),
+                region(0x32, 0x34, Some("LOOP")),
+            ],
+            map.code
+        );
+        assert_eq!(vec![region(2, 0x30, None), region(0x34, 2048, None)], map.gaps);
+        assert_eq!(
+            vec![region(0x30, 0x31, Some("COUNT")), region(0x40, 0x50, Some("BUFFER"))],
+            map.ram
+        );
+        assert_eq!(vec![region(1, 2, Some("READY"))], map.bits);
+        assert!(map.problems().is_empty());
+
+        let map = MemoryMap::of(&rows, Some(Device { code: 4, ..DEVICES[0] }));
+        assert_eq!(
+            vec![
+                "code needs 6 bytes but the device has 4",
+                "code at 0030H lies beyond the end of the flash",
+            ],
+            map.problems()
+        );
+    }
+}
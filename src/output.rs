@@ -0,0 +1,814 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use crate::{
+    batch::FileSummary,
+    cfg::{write_dot, Cfg},
+    explain::write_explanations,
+    memory_map::{Device, MemoryMap, Region},
+    peephole::{suggest, Suggestion},
+    report::{cycle_time, routines, AnalysisOptions, Routine, RoutineMode, Row, Statistics, Totals},
+    segment::Space,
+    stack::{initial_sp, StackReport, RESET_SP},
+    wcet::{wcet, Wcet},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    File,
+    Line,
+    Space,
+    Address,
+    Label,
+    Instruction,
+    Modes,
+    Bytes,
+    Cycles,
+    Time,
+    Encoding,
+    Comment,
+    Error,
+}
+
+impl Column {
+    pub const ALL: [Column; 13] = [
+        Column::File,
+        Column::Line,
+        Column::Space,
+        Column::Address,
+        Column::Label,
+        Column::Instruction,
+        Column::Modes,
+        Column::Bytes,
+        Column::Cycles,
+        Column::Time,
+        Column::Encoding,
+        Column::Comment,
+        Column::Error,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::File => "file",
+            Column::Line => "line",
+            Column::Space => "space",
+            Column::Address => "address",
+            Column::Label => "label",
+            Column::Instruction => "instruction",
+            Column::Modes => "modes",
+            Column::Bytes => "bytes",
+            Column::Cycles => "cycles",
+            Column::Time => "time",
+            Column::Encoding => "encoding",
+            Column::Comment => "comment",
+            Column::Error => "error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Column::File => "File",
+            Column::Line => "Line",
+            Column::Space => "Space",
+            Column::Address => "Address",
+            Column::Label => "Label",
+            Column::Instruction => "Instruction",
+            Column::Modes => "Modes",
+            Column::Bytes => "Bytes",
+            Column::Cycles => "Cycles",
+            Column::Time => "Time (us)",
+            Column::Encoding => "Encoding",
+            Column::Comment => "Comment",
+            Column::Error => "Error",
+        }
+    }
+
+    pub fn cell(&self, row: &Row, clock: f64) -> String {
+        match self {
+            Column::File => row
+                .file
+                .as_ref()
+                .map(|file| file.display().to_string())
+                .unwrap_or_default(),
+            Column::Line => row.line.to_string(),
+            Column::Space => row.space.name().to_string(),
+            Column::Address => format!("{:04X}", row.address),
+            Column::Label => row.label.clone().unwrap_or_default(),
+            Column::Instruction => instruction_cell(row),
+            Column::Modes => modes_cell(row),
+            Column::Bytes => bytes_cell(row),
+            Column::Cycles => cycles_cell(row),
+            Column::Time => match &row.cycles {
+                Ok(cycles) => format!("{:.3}", cycle_time(*cycles, clock) * 1e6),
+                Err(_) => "".to_string(),
+            },
+            Column::Encoding => encoding_cell(row),
+            Column::Comment => row.comment.clone().unwrap_or_default(),
+            Column::Error => match (&row.problem, &row.bytes, &row.cycles) {
+                (Some(problem), _, _) => problem.clone(),
+                (_, Err(err), _) => err.to_string(),
+                (_, _, Err(err)) => err.to_string(),
+                _ => "".to_string(),
+            },
+        }
+    }
+}
+
+/// Parses a comma separated list of column names, as given to `--columns`.
+pub fn parse_columns(text: &str) -> Result<Vec<Column>, String> {
+    text.split(',')
+        .map(str::trim)
+        .map(|name| {
+            Column::ALL
+                .into_iter()
+                .find(|column| column.name() == name)
+                .ok_or_else(|| {
+                    let names = Column::ALL.map(|column| column.name());
+                    format!("unknown column `{}`, expected one of {}", name, names.join(", "))
+                })
+        })
+        .collect()
+}
+
+/// Parses a single character delimiter, accepting `\t` and `tab` for TSV.
+pub fn parse_delimiter(text: &str) -> Result<u8, String> {
+    match text {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if text.len() == 1 && text.is_ascii() => Ok(text.as_bytes()[0]),
+        _ => Err(String::from("the delimiter must be a single ASCII character")),
+    }
+}
+
+pub struct TableOptions {
+    pub address: bool,
+    pub encoding: bool,
+    pub totals: bool,
+    pub columns: Vec<Column>,
+    pub delimiter: u8,
+    /// Oscillator frequency in Hz.
+    pub clock: f64,
+    /// Adds a subtotal block per routine when set.
+    pub routines: Option<RoutineMode>,
+    /// Adds the memory map after the statistics.
+    pub memory_map: bool,
+    /// The device whose memory sizes the map checks against.
+    pub device: Option<Device>,
+    /// Adds the call graph and worst-case stack depth after the statistics.
+    pub stack: bool,
+    /// Initial `SP`, taken from the first `MOV SP, #value` when not given.
+    pub initial_sp: Option<usize>,
+    /// Adds the worst-case execution time of every routine after the statistics.
+    pub wcet: bool,
+    /// Iteration bounds of loops by the label they start at, as given to `--loop-bound`.
+    pub loop_bounds: Vec<(String, usize)>,
+    /// Adds peephole optimization suggestions after the statistics.
+    pub suggest: bool,
+    /// The options the rows were analyzed with.
+    pub analysis: AnalysisOptions,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            address: false,
+            encoding: false,
+            totals: false,
+            columns: vec![Column::Instruction, Column::Modes, Column::Bytes, Column::Cycles],
+            delimiter: b',',
+            clock: 12e6,
+            routines: None,
+            memory_map: false,
+            device: None,
+            stack: false,
+            initial_sp: None,
+            wcet: false,
+            loop_bounds: vec![],
+            suggest: false,
+            analysis: AnalysisOptions::default(),
+        }
+    }
+}
+
+pub fn modes_cell(row: &Row) -> String {
+    match &row.modes {
+        Ok(modes) => modes
+            .iter()
+            .map(|mode| format!("{:?}", mode))
+            .collect::<Vec<String>>()
+            .join(", "),
+        Err(_) => "".to_string(),
+    }
+}
+
+pub fn encoding_cell(row: &Row) -> String {
+    row.encoding
+        .iter()
+        .flatten()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Bytes of the row, or in parentheses those of the macro expansion it invokes. Lines
+/// left out by conditional assembly show `-`.
+pub fn bytes_cell(row: &Row) -> String {
+    match (&row.expansion, &row.bytes) {
+        _ if row.inactive => "-".to_string(),
+        (Some(expansion), _) => format!("({})", expansion.bytes),
+        (None, Ok(bytes)) => bytes.to_string(),
+        (None, Err(_)) => "-1".to_string(),
+    }
+}
+
+/// The instruction as written, followed by the form a generic `JMP` or `CALL` took.
+pub fn instruction_cell(row: &Row) -> String {
+    match row.resolved() {
+        Some(mnemonic) => format!("{} ({})", row.instruction, mnemonic),
+        None => row.instruction.clone(),
+    }
+}
+
+/// Cycles of the row, or in parentheses those of the macro expansion it invokes. Lines
+/// left out by conditional assembly show `-`.
+pub fn cycles_cell(row: &Row) -> String {
+    match (&row.expansion, &row.cycles) {
+        _ if row.inactive => "-".to_string(),
+        (Some(expansion), _) => format!("({})", expansion.cycles),
+        (None, Ok(cycles)) => cycles.to_string(),
+        (None, Err(_)) => "-1".to_string(),
+    }
+}
+
+/// Cells of the terminal and LaTeX tables, which indent macro expansions under their
+/// invocation.
+fn to_cells(row: &Row) -> [String; 4] {
+    [
+        format!("{}{}", "  ".repeat(row.macro_depth), instruction_cell(row)),
+        modes_cell(row),
+        bytes_cell(row),
+        cycles_cell(row),
+    ]
+}
+
+/// Writes `rows` in the given format: `table`, `csv`, `latex`, `dot` or `explain`.
+pub fn write<W: Write>(rows: &[Row], format: &str, options: &TableOptions, writer: W) -> io::Result<()> {
+    match format {
+        "csv" => Ok(write_csv(rows, options, writer)?),
+        "latex" => write_latex(rows, options, writer),
+        "dot" => write_dot(&Cfg::of(rows), rows, writer),
+        "explain" => write_explanations(rows, &options.analysis, writer),
+        _ => write_table(rows, options, writer),
+    }
+}
+
+const ROUTINE_HEADER: [&str; 5] = ["Routine", "Lines", "Bytes", "Cycles", "Time (us)"];
+
+fn routine_cells(routine: &Routine, clock: f64) -> [String; 5] {
+    [
+        routine.name.clone(),
+        format!("{}-{}", routine.first_line, routine.last_line),
+        routine.bytes.to_string(),
+        routine.cycles.to_string(),
+        format!("{:.3}", cycle_time(routine.cycles, clock) * 1e6),
+    ]
+}
+
+pub fn write_csv<W: Write>(rows: &[Row], options: &TableOptions, writer: W) -> csv::Result<()> {
+    let mut builder = csv::WriterBuilder::new();
+    builder.delimiter(options.delimiter);
+    let mut writer = builder.from_writer(writer);
+    writer.write_record(options.columns.iter().map(Column::title))?;
+    for row in rows {
+        writer.write_record(options.columns.iter().map(|column| column.cell(row, options.clock)))?;
+    }
+    if let Some(mode) = options.routines {
+        let mut inner = writer.into_inner().map_err(|err| err.into_error())?;
+        inner.write_all(b"\n")?;
+        writer = builder.from_writer(inner);
+        writer.write_record(ROUTINE_HEADER)?;
+        for routine in routines(rows, mode) {
+            writer.write_record(routine_cells(&routine, options.clock))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_table<W: Write>(rows: &[Row], options: &TableOptions, mut writer: W) -> io::Result<()> {
+    let res = rows.iter().map(to_cells).collect::<Vec<_>>();
+    let mut max_size: (usize, usize, usize, usize) = (
+        "Instruction".len(),
+        "Modes".len(),
+        "Memory".len(),
+        "Cycles".len(),
+    );
+
+    for line in &res {
+        let to_size = |tuple: &[String; 4]| {
+            (
+                tuple[0].len(),
+                tuple[1].len(),
+                tuple[2].len(),
+                tuple[3].len(),
+            )
+        };
+
+        let sizes = to_size(line);
+
+        if max_size.0 < sizes.0 {
+            max_size.0 = sizes.0
+        }
+
+        if max_size.1 < sizes.1 {
+            max_size.1 = sizes.1
+        }
+
+        if max_size.2 < sizes.2 {
+            max_size.2 = sizes.2
+        }
+
+        if max_size.3 < sizes.3 {
+            max_size.3 = sizes.3
+        }
+    }
+
+    writeln!(
+        writer,
+        "{:^len1$}  {:^len2$}  {:^len3$}  {:^len4$}",
+        "Instruction",
+        "Modes",
+        "Memory",
+        "Cycles",
+        len1 = max_size.0,
+        len2 = max_size.1,
+        len3 = max_size.2,
+        len4 = max_size.3
+    )?;
+    for line in res {
+        writeln!(
+            writer,
+            "{:<len1$}: {:>len2$}, {:>len3$}, {:>len4$}",
+            line[0],
+            line[1],
+            line[2],
+            line[3],
+            len1 = max_size.0,
+            len2 = max_size.1,
+            len3 = max_size.2,
+            len4 = max_size.3
+        )?;
+    }
+    if let Some(mode) = options.routines {
+        write_routines(&routines(rows, mode), options.clock, &mut writer)?;
+    }
+    write_statistics(&Statistics::of(rows), &mut writer)?;
+    if options.memory_map {
+        write_memory_map(&MemoryMap::of(rows, options.device), &mut writer)?;
+    }
+    if options.stack {
+        write_stack(&stack_report(rows, options), &mut writer)?;
+    }
+    if options.wcet {
+        write_wcet(&wcet(rows, &options.loop_bounds), options.clock, &mut writer)?;
+    }
+    if options.suggest {
+        write_suggestions(rows, &suggest(rows, &options.analysis), &mut writer)?;
+    }
+    Ok(())
+}
+
+/// The stack analysis of `rows` for the configured device and initial `SP`.
+pub fn stack_report(rows: &[Row], options: &TableOptions) -> StackReport {
+    let sp = options
+        .initial_sp
+        .or_else(|| initial_sp(rows))
+        .unwrap_or(RESET_SP);
+    let ram = options.device.map_or(128, |device| device.ram);
+    StackReport::of(rows, sp, ram)
+}
+
+fn write_routines<W: Write>(routines: &[Routine], clock: f64, writer: &mut W) -> io::Result<()> {
+    let cells = routines
+        .iter()
+        .map(|routine| routine_cells(routine, clock))
+        .collect::<Vec<_>>();
+    let width = cells
+        .iter()
+        .map(|line| line[0].len())
+        .max()
+        .unwrap_or(0)
+        .max(ROUTINE_HEADER[0].len());
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:<width$}  {:>9}  {:>6}  {:>6}  {:>9}",
+        ROUTINE_HEADER[0],
+        ROUTINE_HEADER[1],
+        ROUTINE_HEADER[2],
+        ROUTINE_HEADER[3],
+        ROUTINE_HEADER[4],
+        width = width
+    )?;
+    for line in cells {
+        writeln!(
+            writer,
+            "{:<width$}  {:>9}  {:>6}  {:>6}  {:>9}",
+            line[0],
+            line[1],
+            line[2],
+            line[3],
+            line[4],
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+fn write_histogram<W: Write>(title: &str, counts: &BTreeMap<String, usize>, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "{}:", title)?;
+    let width = counts.keys().map(String::len).max().unwrap_or(0);
+    for (name, count) in counts {
+        writeln!(
+            writer,
+            "  {:<width$}  {:>4}  {}",
+            name,
+            count,
+            "#".repeat(*count),
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_statistics<W: Write>(stats: &Statistics, mut writer: W) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "Code bytes:    {}", stats.code_bytes)?;
+    writeln!(writer, "Data bytes:    {}", stats.data_bytes)?;
+    writeln!(writer, "Cycles:        {}", stats.cycles)?;
+    writeln!(writer, "Instructions:  {}", stats.instructions)?;
+    writeln!(writer, "Invalid lines: {}", stats.invalid)?;
+    writeln!(writer)?;
+    writeln!(writer, "Memory usage:")?;
+    for (space, used) in &stats.usage {
+        if *space == Space::Code || *used > 0 {
+            writeln!(writer, "  {:<6} {:>6} {}", space.name(), used, space.unit())?;
+        }
+    }
+    writeln!(writer)?;
+    write_histogram("Addressing modes", &stats.modes, &mut writer)?;
+    writeln!(writer)?;
+    write_histogram("Mnemonics", &stats.mnemonics, &mut writer)
+}
+
+fn write_regions<W: Write>(title: &str, regions: &[Region], unit: &str, writer: &mut W) -> io::Result<()> {
+    if regions.is_empty() {
+        return Ok(());
+    }
+    writeln!(writer, "  {}:", title)?;
+    for region in regions {
+        let line = format!(
+            "    {:04X}-{:04X}  {:>5} {:<5}  {}",
+            region.start,
+            region.end - 1,
+            region.len(),
+            unit,
+            region.name.as_deref().unwrap_or("")
+        );
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+pub fn write_memory_map<W: Write>(map: &MemoryMap, mut writer: W) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "Memory map:")?;
+    write_regions("Code", &map.code, "bytes", &mut writer)?;
+    write_regions("Free code", &map.gaps, "bytes", &mut writer)?;
+    write_regions("Internal RAM", &map.ram, "bytes", &mut writer)?;
+    write_regions("Bit space", &map.bits, "bits", &mut writer)?;
+    let Some(device) = map.device else {
+        return Ok(());
+    };
+    writeln!(writer, "  Usage on {}:", device.name)?;
+    for capacity in map.capacities.iter().filter(|capacity| capacity.size + capacity.used > 0) {
+        writeln!(
+            writer,
+            "    {:<12}  {:>5} of {:>5} {:<5}  {:>5.1}%",
+            capacity.space,
+            capacity.used,
+            capacity.size,
+            capacity.unit,
+            capacity.percent()
+        )?;
+    }
+    for problem in map.problems() {
+        writeln!(writer, "Error: {}", problem)?;
+    }
+    Ok(())
+}
+
+fn depth_cell(depth: Option<usize>) -> String {
+    depth.map_or(String::from("unbounded"), |depth| depth.to_string())
+}
+
+pub fn write_stack<W: Write>(report: &StackReport, mut writer: W) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "Stack:")?;
+    writeln!(writer, "  {:<12}  {:>6}  {:>9}", "Entry point", "Vector", "Depth")?;
+    for point in &report.entry_points {
+        writeln!(
+            writer,
+            "  {:<12}  {:04X}H  {:>9}",
+            point.name,
+            point.vector,
+            depth_cell(point.depth)
+        )?;
+    }
+    writeln!(writer, "  Call graph:")?;
+    let roots = report
+        .entry_points
+        .iter()
+        .map(|point| (point.name.to_string(), &point.calls, point.depth));
+    let functions = report
+        .functions
+        .iter()
+        .map(|function| (function.name.clone(), &function.calls, function.depth));
+    for (name, calls, depth) in roots.chain(functions) {
+        let mut line = format!("    {} ({})", name, depth_cell(depth));
+        if !calls.is_empty() {
+            let callees = calls.iter().map(|&entry| report.name(entry)).collect::<Vec<_>>();
+            line.push_str(&format!(" -> {}", callees.join(", ")));
+        }
+        writeln!(writer, "{}", line)?;
+    }
+    writeln!(
+        writer,
+        "  Worst case: {} of {} bytes above SP={:02X}H",
+        depth_cell(report.worst_case),
+        report.available,
+        report.initial_sp
+    )?;
+    if report.overflows() {
+        writeln!(writer, "Warning: the stack may overflow the internal RAM")?;
+    }
+    Ok(())
+}
+
+pub fn write_wcet<W: Write>(routines: &[Wcet], clock: f64, mut writer: W) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "Worst-case execution time:")?;
+    let width = routines
+        .iter()
+        .map(|routine| routine.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Routine".len());
+    writeln!(writer, "  {:<width$}  {:>8}  {:>10}", "Routine", "Cycles", "Time (us)", width = width)?;
+    for routine in routines {
+        match &routine.cycles {
+            Ok(cycles) => writeln!(
+                writer,
+                "  {:<width$}  {:>8}  {:>10.3}",
+                routine.name,
+                cycles,
+                cycle_time(*cycles, clock) * 1e6,
+                width = width
+            )?,
+            Err(reason) => writeln!(
+                writer,
+                "  {:<width$}  unbounded: {}",
+                routine.name,
+                reason,
+                width = width
+            )?,
+        }
+    }
+    Ok(())
+}
+
+pub fn write_suggestions<W: Write>(rows: &[Row], suggestions: &[Suggestion], mut writer: W) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "Suggestions:")?;
+    for suggestion in suggestions {
+        let original = suggestion
+            .rows
+            .iter()
+            .map(|&index| rows[index].statement.as_str())
+            .collect::<Vec<_>>();
+        writeln!(
+            writer,
+            "  line {}: {} -> {}  (saves {} bytes, {} cycles) {}",
+            rows[suggestion.rows[0]].line,
+            original.join(" / "),
+            suggestion.replacement.as_deref().unwrap_or("(remove)"),
+            suggestion.bytes,
+            suggestion.cycles,
+            suggestion.reason
+        )?;
+    }
+    let bytes = suggestions.iter().map(|suggestion| suggestion.bytes).sum::<usize>();
+    let cycles = suggestions.iter().map(|suggestion| suggestion.cycles).sum::<usize>();
+    writeln!(writer, "  Total: saves {} bytes, {} cycles", bytes, cycles)
+}
+
+pub fn latex_escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' | '_' | '$' | '%' | '&' | '{' | '}' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '@' => res.push_str("{@}"),
+            '\\' => res.push_str("\\textbackslash{}"),
+            '~' => res.push_str("\\textasciitilde{}"),
+            '^' => res.push_str("\\textasciicircum{}"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// Writes a `longtable` environment meant to be pulled into a document with `\input`.
+pub fn write_latex<W: Write>(rows: &[Row], options: &TableOptions, mut writer: W) -> io::Result<()> {
+    let mut header = vec![];
+    if options.address {
+        header.push(("r", "Address"));
+    }
+    header.extend([("l", "Instruction"), ("l", "Modes"), ("r", "Bytes"), ("r", "Cycles")]);
+    if options.encoding {
+        header.push(("l", "Encoding"));
+    }
+    let spec = header.iter().map(|(align, _)| *align).collect::<Vec<_>>().join("");
+    let titles = header
+        .iter()
+        .map(|(_, title)| format!("\\textbf{{{}}}", title))
+        .collect::<Vec<_>>()
+        .join(" & ");
+
+    writeln!(writer, "\\begin{{longtable}}{{{}}}", spec)?;
+    writeln!(writer, "\\hline")?;
+    writeln!(writer, "{} \\\\", titles)?;
+    writeln!(writer, "\\hline")?;
+    writeln!(writer, "\\endhead")?;
+    for row in rows {
+        let [instruction, modes, bytes, cycles] = to_cells(row);
+        let mut cells = vec![];
+        if options.address {
+            cells.push(format!("\\texttt{{{:04X}}}", row.address));
+        }
+        cells.extend([
+            format!("\\texttt{{{}}}", latex_escape(&instruction)),
+            latex_escape(&modes),
+            bytes,
+            cycles,
+        ]);
+        if options.encoding {
+            cells.push(format!("\\texttt{{{}}}", encoding_cell(row)));
+        }
+        writeln!(writer, "{} \\\\", cells.join(" & "))?;
+    }
+    writeln!(writer, "\\hline")?;
+    if options.totals {
+        let totals = Totals::of(rows);
+        let mut cells = vec![String::from("\\textbf{Total}"), String::new()];
+        if options.address {
+            cells.push(String::new());
+        }
+        cells.extend([totals.bytes.to_string(), totals.cycles.to_string()]);
+        if options.encoding {
+            cells.push(String::new());
+        }
+        writeln!(writer, "{} \\\\", cells.join(" & "))?;
+        writeln!(writer, "\\hline")?;
+    }
+    writeln!(writer, "\\end{{longtable}}")?;
+    if let Some(mode) = options.routines {
+        writeln!(writer)?;
+        writeln!(writer, "\\begin{{longtable}}{{lrrrr}}")?;
+        writeln!(writer, "\\hline")?;
+        let titles = ROUTINE_HEADER.map(|title| format!("\\textbf{{{}}}", title));
+        writeln!(writer, "{} \\\\", titles.join(" & "))?;
+        writeln!(writer, "\\hline")?;
+        writeln!(writer, "\\endhead")?;
+        for routine in routines(rows, mode) {
+            let [name, lines, bytes, cycles, time] = routine_cells(&routine, options.clock);
+            let name = format!("\\texttt{{{}}}", latex_escape(&name));
+            writeln!(writer, "{} & {} & {} & {} & {} \\\\", name, lines, bytes, cycles, time)?;
+        }
+        writeln!(writer, "\\hline")?;
+        writeln!(writer, "\\end{{longtable}}")?;
+    }
+    Ok(())
+}
+
+/// Writes the combined per-file summary of a batch run in the given format.
+pub fn write_summary<W: Write>(
+    summaries: &[FileSummary],
+    format: &str,
+    options: &TableOptions,
+    mut writer: W,
+) -> io::Result<()> {
+    let mut res = summaries
+        .iter()
+        .map(|summary| {
+            [
+                summary.path.display().to_string(),
+                summary.bytes.to_string(),
+                summary.cycles.to_string(),
+                summary.errors.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    res.push([
+        String::from("Total"),
+        summaries.iter().map(|summary| summary.bytes).sum::<usize>().to_string(),
+        summaries.iter().map(|summary| summary.cycles).sum::<usize>().to_string(),
+        summaries.iter().map(|summary| summary.errors).sum::<usize>().to_string(),
+    ]);
+    let header = ["File", "Bytes", "Cycles", "Errors"];
+
+    match format {
+        "csv" => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(options.delimiter)
+                .from_writer(writer);
+            writer.write_record(header)?;
+            for line in res {
+                writer.write_record(line)?;
+            }
+            writer.flush()
+        }
+        "latex" => {
+            writeln!(writer, "\\begin{{longtable}}{{lrrr}}")?;
+            writeln!(writer, "\\hline")?;
+            let titles = header.map(|title| format!("\\textbf{{{}}}", title));
+            writeln!(writer, "{} \\\\", titles.join(" & "))?;
+            writeln!(writer, "\\hline")?;
+            writeln!(writer, "\\endhead")?;
+            let total = res.pop().unwrap();
+            for [file, bytes, cycles, errors] in res {
+                let file = format!("\\texttt{{{}}}", latex_escape(&file));
+                writeln!(writer, "{} & {} & {} & {} \\\\", file, bytes, cycles, errors)?;
+            }
+            writeln!(writer, "\\hline")?;
+            let [_, bytes, cycles, errors] = total;
+            writeln!(writer, "\\textbf{{Total}} & {} & {} & {} \\\\", bytes, cycles, errors)?;
+            writeln!(writer, "\\hline")?;
+            writeln!(writer, "\\end{{longtable}}")
+        }
+        _ => {
+            let width = res
+                .iter()
+                .map(|line| line[0].len())
+                .max()
+                .unwrap_or(0)
+                .max(header[0].len());
+            writeln!(
+                writer,
+                "{:<width$}  {:>8}  {:>8}  {:>8}",
+                header[0],
+                header[1],
+                header[2],
+                header[3],
+                width = width
+            )?;
+            for line in res {
+                writeln!(
+                    writer,
+                    "{:<width$}  {:>8}  {:>8}  {:>8}",
+                    line[0],
+                    line[1],
+                    line[2],
+                    line[3],
+                    width = width
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        assert_eq!(
+            Ok(vec![Column::Address, Column::Instruction, Column::Time]),
+            parse_columns("address, instruction,time")
+        );
+        assert!(parse_columns("instruction,size").is_err());
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            "MOV A, \\#30H ; R\\_0 \\$ {@}R0",
+            latex_escape("MOV A, #30H ; R_0 $ @R0")
+        );
+    }
+}
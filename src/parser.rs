@@ -1,18 +1,24 @@
 use std::collections::HashMap;
 
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    dialect::Dialect,
+    literal::{literal_pattern, LiteralDialect},
+};
 
 const INSTRUCTIONS: &str = r#"
 {
 	"NOP": [],
-	"AJMP": [["addr1B"]],
+	"AJMP": [["addr11"]],
+	"LJMP": [["addr2B"]],
 	"RR": [["A"]],
 	"INC": [["A"], ["addr1B"], ["@Ri"], ["Rn"], ["DPTR"]],
 	"JBC": [["bit", "rel1B"]],
-	"ACALL": [["addr1B"]],
+	"ACALL": [["addr11"]],
 	"LCALL": [["addr2B"]],
 	"RRC": [["A"]],
-	"DEC": [["A"], ["@Ri"], ["Rn"]],
+	"DEC": [["A"], ["addr1B"], ["@Ri"], ["Rn"]],
 	"JB": [["bit", "rel1B"]],
 	"RET": [],
 	"RL": [["A"]],
@@ -22,9 +28,9 @@ const INSTRUCTIONS: &str = r#"
 	"RLC": [["A"]],
 	"ADDC": [["A", "imm1B"], ["A", "addr1B"], ["A", "@Ri"], ["A", "Rn"]],
 	"JC": [["rel1B"]],
-	"ORL": [["addr1B", "A"], ["addr1B", "imm1B"], ["A", "imm1B"], ["A", "addr1B"], ["A", "@Ri"], ["A", "Rn"], ["C", "bit"], ["bit", "C"]],
+	"ORL": [["addr1B", "A"], ["addr1B", "imm1B"], ["A", "imm1B"], ["A", "addr1B"], ["A", "@Ri"], ["A", "Rn"], ["C", "bit"], ["C", "/bit"], ["bit", "C"]],
 	"JNC": [["rel1B"]],
-	"ANL": [["addr1B", "A"], ["addr1B", "imm1B"], ["A", "imm1B"], ["A", "addr1B"], ["A", "@Ri"], ["A", "Rn"], ["C", "bit"], ["bit", "C"]],
+	"ANL": [["addr1B", "A"], ["addr1B", "imm1B"], ["A", "imm1B"], ["A", "addr1B"], ["A", "@Ri"], ["A", "Rn"], ["C", "bit"], ["C", "/bit"], ["bit", "C"]],
 	"JZ": [["rel1B"]],
 	"XRL": [["addr1B", "A"], ["addr1B", "imm1B"], ["A", "imm1B"], ["A", "addr1B"], ["A", "@Ri"], ["A", "Rn"]],
 	"JNZ": [["rel1B"]],
@@ -63,62 +69,125 @@ pub fn get_all_inst_variants() -> HashMap<String, Vec<Vec<String>>> {
     ron::from_str(INSTRUCTIONS).expect("Error when parsing instructions.ron")
 }
 
+/// Operands, registers and directives are matched regardless of case, as assemblers do.
+fn build(pattern: &str) -> Regex {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .unwrap()
+}
+
 pub fn get_regex() -> HashMap<String, Regex> {
+    get_dialect_regex(Dialect::default(), LiteralDialect::default())
+}
+
+/// A symbol name that cannot be mistaken for a register: any identifier except `A`, `C`,
+/// `AB`, `PC`, `DPTR` and `R` followed by a digit. The regex crate has no lookahead, so
+/// the reserved words are excluded length by length.
+///
+/// Hex literals missing their leading zero, like `FFH`, match too, as they are valid names.
+/// Unless such a symbol is defined, the analysis reports the row as an error.
+fn symbol_pattern() -> String {
+    let c = "[A-Z0-9_$?]";
+    [
+        String::from("[BD-Z_?]"),
+        format!("[B-OQS-Z_?]{c}|A[AC-Z0-9_$?]|P[ABD-Z0-9_$?]|R[A-Z_$?]"),
+        format!("[A-Z_?]{c}{{2}}"),
+        format!("[A-CE-Z_?]{c}{{3}}|D[A-OQ-Z0-9_$?]{c}{{2}}|DP[A-SU-Z0-9_$?]{c}|DPT[A-QS-Z0-9_$?]"),
+        format!("[A-Z_?]{c}{{4,}}"),
+        // SDCC's local labels, e.g. `00101$`.
+        String::from(r"[0-9]+\$"),
+    ]
+    .join("|")
+}
+
+pub fn get_dialect_regex(dialect: Dialect, literals: LiteralDialect) -> HashMap<String, Regex> {
+    let num1 = literal_pattern(literals, 1);
+    let num2 = literal_pattern(literals, 2);
+    let symbol = symbol_pattern();
+    // asxxxx selects the low or high byte of a 16-bit value with `#<` and `#>`.
+    let byte_select = match dialect {
+        Dialect::Sdcc => format!("|[<>]({}|{})", num2, symbol),
+        Dialect::A51 | Dialect::Asem51 => String::new(),
+    };
     let mut res = HashMap::new();
-    res.insert(String::from("A"), Regex::new(r"^A$").unwrap());
-    res.insert(String::from("AB"), Regex::new(r"^AB$").unwrap());
-    res.insert(String::from("C"), Regex::new(r"^C$").unwrap());
-    res.insert(String::from("DPTR"), Regex::new(r"^DPTR$").unwrap());
-    res.insert(
-        String::from("@A+DPTR"),
-        Regex::new(r"^@A *\+ *DPTR$").unwrap(),
-    );
-    res.insert(String::from("@A+PC"), Regex::new(r"^@A *\+ *PC$").unwrap());
-    res.insert(String::from("@DPTR"), Regex::new(r"^@DPTR$").unwrap());
-    res.insert(String::from("@Ri"), Regex::new(r"^@R[0-1]$").unwrap());
-    res.insert(String::from("Rn"), Regex::new(r"^R[0-7]$").unwrap());
+    res.insert(String::from("A"), build(r"^A$"));
+    res.insert(String::from("AB"), build(r"^AB$"));
+    res.insert(String::from("C"), build(r"^C$"));
+    res.insert(String::from("DPTR"), build(r"^DPTR$"));
+    res.insert(String::from("@A+DPTR"), build(r"^@A *\+ *DPTR$"));
+    res.insert(String::from("@A+PC"), build(r"^@A *\+ *PC$"));
+    res.insert(String::from("@DPTR"), build(r"^@DPTR$"));
+    res.insert(String::from("@Ri"), build(r"^@R[0-1]$"));
+    res.insert(String::from("Rn"), build(r"^R[0-7]$"));
     res.insert(
         String::from("addr1B"),
-        Regex::new(r"^((0*([1-9][A-F0-9]|0[0-9A-F]{1,2})H)|(0*[0-1]{1,8}B)|(0*[0-9]{1,3}D?)|B|(TMOD|T(L|H)[0-1]|SCON|PCON|SBUF))$").unwrap(),
+        build(&format!(r"^({}|{})$", num1, symbol)),
     );
     res.insert(
         String::from("imm1B"),
-        Regex::new(r"^#((0*([1-9][A-F0-9]|0[0-9A-F]{1,2})H)|(0*[0-1]{1,8}B)|(-?0*[0-9]{1,3}D?))$")
-            .unwrap(),
+        build(&format!(r"^#(-?{}|{}{})$", num1, symbol, byte_select)),
     );
     res.insert(
-        String::from("addr2B"),
-        Regex::new(
-            r"^((0*([1-9][A-F0-9]{1,3}|0[0-9A-F]{1,4})H)|(0*[0-1]{1,16}B)|(0*[0-9]{1,5}D?)|B)$",
-        )
-        .unwrap(),
+        String::from("addr11"),
+        build(&format!(r"^({}|{})$", num2, symbol)),
     );
     res.insert(
-        String::from("imm2B"),
-        Regex::new(
-            r"^#((0*([1-9][A-F0-9]{1,3}|0[0-9A-F]{1,4})H)|(0*[0-1]{1,16}B)|(0*[0-9]{1,5}D?))$",
-        )
-        .unwrap(),
+        String::from("addr2B"),
+        build(&format!(r"^({}|{})$", num2, symbol)),
     );
+    res.insert(String::from("imm2B"), build(&format!(r"^#({}|{})$", num2, symbol)));
     res.insert(
         String::from("rel1B"),
-        Regex::new(r"^((0*([1-9][A-F0-9]|0[0-9A-F]{1,2})H)|(0*[0-1]{1,8}B)|(0*[0-9]{1,3}D?)|([A-Z][A-Z0-9_-]*))$").unwrap(),
+        build(&format!(r"^({}|{})$", num1, symbol)),
     );
     res.insert(
         String::from("bit"),
-        Regex::new(r"^((0*([1-9][A-F0-9]|0[0-9A-F]{1,2})H)|(0*[0-1]{1,8}B)|(0*[0-9]{1,3}D?)|((P[0-7]|ACC).[0-7])|(T(F|R)[0-1])|((T|R)I))$").unwrap(),
+        build(&format!(r"^({}|{}|({}|{})\.[0-7])$", num1, symbol, num1, symbol)),
+    );
+    // The complement of a bit, as read by `ANL C, /bit` and `ORL C, /bit`.
+    res.insert(
+        String::from("/bit"),
+        build(&format!(r"^/({}|{}|({}|{})\.[0-7])$", num1, symbol, num1, symbol)),
     );
 
     res
 }
 
 pub fn get_skip_list() -> Vec<Regex> {
-    vec![
-        Regex::new(r"^END$").unwrap(),
-        Regex::new(r"^ORG.+$").unwrap(),
-        Regex::new(r"^DB.+$").unwrap(),
-        Regex::new(r"^.+EQU.+$").unwrap(),
-    ]
+    get_dialect_skip_list(Dialect::default())
+}
+
+/// Directives the dialect accepts, matched against statements already rewritten by
+/// [`Dialect::canonical`].
+pub fn get_dialect_skip_list(dialect: Dialect) -> Vec<Regex> {
+    let mut res = vec![
+        build(r"^END$"),
+        build(r"^ORG.+$"),
+        build(r"^D[BWS] .+$"),
+        build(r"^DBIT .+$"),
+        build(&format!(
+            r"^\S+ ({})\s.+$",
+            dialect.symbol_directives().join("|")
+        )),
+    ];
+    if !dialect.ignored_directives().is_empty() {
+        res.push(build(&format!(
+            r"^({})\b.*$",
+            dialect.ignored_directives().join("|")
+        )));
+    }
+    res.push(match dialect {
+        // `$NOMOD51`, `$INCLUDE (REG51.INC)` and other controls.
+        Dialect::A51 | Dialect::Asem51 => build(r"^\$.*$"),
+        // `.area`, `.globl`, `.module` and the rest only steer the linker.
+        Dialect::Sdcc => build(r"^\..*$"),
+    });
+    if dialect == Dialect::A51 {
+        // `VARS SEGMENT DATA` declares a relocatable segment.
+        res.push(build(r"^\S+ SEGMENT\s.+$"));
+    }
+    res
 }
 
 pub fn is_valid(
@@ -150,7 +219,9 @@ pub fn is_valid(
     if skip_list.iter().any(|reg_pat| reg_pat.is_match(line)) {
         return Ok(());
     }
-    let all_operands = all_inst.get(instruction).ok_or(ParseError)?;
+    let all_operands = all_inst
+        .get(&instruction.to_ascii_uppercase())
+        .ok_or(ParseError)?;
     if all_operands.is_empty() {
         return raw_operands
             .trim()
@@ -160,6 +231,9 @@ pub fn is_valid(
     }
     let operands = raw_operands.split(',').map(str::trim);
     for ops in all_operands {
+        if ops.len() != operands.clone().count() {
+            continue;
+        }
         let mut is_match = true;
         for (op1, op2) in ops.iter().zip(operands.clone()) {
             if !regex_map.get(op1).unwrap().is_match(op2) {
@@ -173,3 +247,32 @@ pub fn is_valid(
     }
     Err(ParseError)
 }
+
+static NO_OPERANDS: Vec<String> = Vec::new();
+
+pub fn find_variant<'a>(
+    instruction: &str,
+    raw_operands: &str,
+    all_inst: &'a HashMap<String, Vec<Vec<String>>>,
+    regex_map: &HashMap<String, Regex>,
+) -> Option<&'a Vec<String>> {
+    let operands = raw_operands
+        .split(',')
+        .map(str::trim)
+        .filter(|op| !op.is_empty())
+        .collect::<Vec<_>>();
+    let variants = all_inst.get(&instruction.to_ascii_uppercase())?;
+    // Instructions without operands, like `RET`, list no variants.
+    if variants.is_empty() && operands.is_empty() {
+        return Some(&NO_OPERANDS);
+    }
+    variants
+        .iter()
+        .find(|ops| {
+            ops.len() == operands.len()
+                && ops
+                    .iter()
+                    .zip(&operands)
+                    .all(|(op1, op2)| regex_map.get(op1).unwrap().is_match(op2))
+        })
+}
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    get_cycle, get_memory,
+    instruction::get_addr_mode_map,
+    matching::make_matcher,
+    parser::{get_all_inst_variants, get_dialect_regex, get_dialect_skip_list},
+    report::{reaches, split_statement, AnalysisOptions, Row},
+    segment::Space,
+};
+
+/// A cheaper equivalent of one or more consecutive instructions.
+pub struct Suggestion {
+    /// Indices of the rows replaced. The first keeps its label.
+    pub rows: Vec<usize>,
+    /// The statement replacing them, `None` when they can simply be removed.
+    pub replacement: Option<String>,
+    pub reason: &'static str,
+    /// Bytes and cycles saved, from the same tables the rows were sized with.
+    pub bytes: usize,
+    pub cycles: usize,
+}
+
+/// Spells `text` in the case of the statement it replaces, as SDCC output is lowercase.
+fn spelled(like: &str, text: &str) -> String {
+    match like.starts_with(|c: char| c.is_ascii_lowercase()) {
+        true => text.to_ascii_lowercase(),
+        false => text.to_string(),
+    }
+}
+
+/// Finds peephole optimizations in the analyzed rows.
+///
+/// Each suggestion is judged on its own. Shortening an instruction moves the code after it,
+/// which can take an `AJMP` or `ACALL` out of its 2 KB page, so [`rewrite`] checks the
+/// suggestions it applies together.
+///
+/// A counted loop is fused into `DJNZ` when it decrements the register and then compares
+/// it with `CJNE Rn, #0`. A `CJNE` without the decrement leaves the register unchanged,
+/// so a `CJNE` and `SJMP` pair has no `DJNZ` equivalent.
+pub fn suggest(rows: &[Row], options: &AnalysisOptions) -> Vec<Suggestion> {
+    let all_inst = get_all_inst_variants();
+    let regex_map = get_dialect_regex(options.dialect, options.literals);
+    let addr_mode_map = get_addr_mode_map();
+    let skip_list = get_dialect_skip_list(options.dialect);
+    let matcher = make_matcher();
+    let cost = |statement: &str| {
+        let bytes = get_memory(statement, &all_inst, &regex_map, &addr_mode_map, &skip_list).ok()?;
+        let cycles = get_cycle(statement, &matcher, &all_inst, &regex_map, &skip_list).ok()?;
+        Some((bytes, cycles))
+    };
+
+    let targets = rows.iter().filter_map(|row| row.target).collect::<HashSet<_>>();
+    let instructions = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| {
+            row.mnemonic.is_some() && row.space == Space::Code && !row.inactive && !row.has_error()
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    // Whether `second` always runs right after `first`, with no label in between.
+    let follows = |first: usize, second: usize| {
+        rows[first + 1..=second].iter().all(|row| row.label.is_none())
+            && !targets.contains(&rows[second].address)
+    };
+
+    let mut res: Vec<Suggestion> = vec![];
+    let mut push = |rows_replaced: Vec<usize>, replacement: Option<String>, reason| {
+        let (bytes, cycles) = rows_replaced.iter().fold((0, 0), |(bytes, cycles), &index| {
+            let row: &Row = &rows[index];
+            (
+                bytes + row.bytes.clone().unwrap_or(0),
+                cycles + row.cycles.clone().unwrap_or(0),
+            )
+        });
+        let (new_bytes, new_cycles) = match &replacement {
+            Some(statement) => match cost(statement) {
+                Some(cost) => cost,
+                None => return,
+            },
+            None => (0, 0),
+        };
+        if new_bytes < bytes || new_cycles < cycles {
+            res.push(Suggestion {
+                rows: rows_replaced,
+                replacement,
+                reason,
+                bytes: bytes.saturating_sub(new_bytes),
+                cycles: cycles.saturating_sub(new_cycles),
+            });
+        }
+    };
+
+    for (position, &index) in instructions.iter().enumerate() {
+        let row = &rows[index];
+        let like = row.statement.as_str();
+        let encoding = row.encoding.as_deref().unwrap_or_default();
+        let operand = row.operands.last().cloned().unwrap_or_default();
+        match encoding {
+            [0x74, 0x00] => push(vec![index], Some(spelled(like, "CLR A")), "CLR A clears A in one byte"),
+            [0x24, 0x01] => push(
+                vec![index],
+                Some(spelled(like, "INC A")),
+                "INC A adds one in one byte, but leaves the flags unchanged",
+            ),
+            [0x02 | 0x12, ..] => {
+                let Some(target) = row.target else {
+                    continue;
+                };
+                let next = row.address + 2;
+                let near = (-128..=127).contains(&(target as i64 - next as i64));
+                let same_page = next & 0xF800 == target & 0xF800;
+                let replacement = match (encoding[0], near, same_page) {
+                    (0x02, true, _) => format!("{} {}", spelled(like, "SJMP"), operand),
+                    (0x02, _, true) => format!("{} {}", spelled(like, "AJMP"), operand),
+                    (0x12, _, true) => format!("{} {}", spelled(like, "ACALL"), operand),
+                    _ => continue,
+                };
+                push(vec![index], Some(replacement), "the target is within reach of a shorter form");
+            }
+            _ => {}
+        }
+
+        let Some(&next) = instructions.get(position + 1).filter(|&&next| follows(index, next)) else {
+            continue;
+        };
+        let second = rows[next].encoding.as_deref().unwrap_or_default();
+        match (encoding, second) {
+            // MOV Rn, A then MOV A, Rn, or the other way round.
+            ([first], [second]) if first ^ second == 0x10 && matches!(first & 0xF8, 0xF8 | 0xE8) => {
+                push(vec![next], None, "A and the register already hold the same value")
+            }
+            // DEC Rn then CJNE Rn, #0, label.
+            ([first], [second, 0x00, _]) if first & 0xF8 == 0x18 && *second == 0xB8 | (first & 7) => {
+                let counter = &row.operands[0];
+                let label = rows[next].operands.last().cloned().unwrap_or_default();
+                let replacement = format!("{} {}, {}", spelled(like, "DJNZ"), counter, label);
+                push(vec![index, next], Some(replacement), "DJNZ decrements and tests in one instruction")
+            }
+            _ => {}
+        }
+    }
+    res
+}
+
+/// The suggestions that can be applied together, leaving out those in included files or
+/// macro expansions.
+///
+/// Code shortened ahead of a jump moves it and, up to the next `ORG`, its target. Until
+/// every `SJMP`, `AJMP` and `ACALL` still reaches its target from the moved address, the
+/// jump's own suggestion, or else the last one moving it or its target, is given up.
+fn compatible<'a>(rows: &[Row], suggestions: &'a [Suggestion]) -> Vec<&'a Suggestion> {
+    let mut res = suggestions
+        .iter()
+        .filter(|suggestion| {
+            suggestion
+                .rows
+                .iter()
+                .all(|&index| rows[index].file.is_none() && rows[index].macro_depth == 0)
+        })
+        .collect::<Vec<_>>();
+    loop {
+        let replaced = res
+            .iter()
+            .flat_map(|suggestion| suggestion.rows.iter().map(move |&index| (index, *suggestion)))
+            .collect::<HashMap<_, _>>();
+        // Bytes saved ahead of each code row since the address was last set.
+        let mut saved = vec![0; rows.len()];
+        let mut end = None;
+        let mut shift = 0;
+        for (index, row) in rows.iter().enumerate() {
+            if row.space != Space::Code || row.inactive {
+                continue;
+            }
+            if end != Some(row.address) {
+                shift = 0;
+            }
+            saved[index] = shift;
+            end = Some(row.address + row.bytes.clone().unwrap_or(0) + row.data_bytes);
+            if let Some(suggestion) = replaced.get(&index).filter(|suggestion| suggestion.rows.last() == Some(&index)) {
+                shift += suggestion.bytes;
+            }
+        }
+        let moved = |address: usize| {
+            rows.iter()
+                .position(|row| row.space == Space::Code && !row.inactive && row.address == address)
+                .map_or((address, None), |index| (address - saved[index], Some(index)))
+        };
+
+        let unreachable = rows.iter().enumerate().find_map(|(index, row)| {
+            let mnemonic = match replaced.get(&index) {
+                Some(suggestion) if suggestion.rows[0] == index => {
+                    suggestion.replacement.as_deref()?.split_whitespace().next()?.to_ascii_uppercase()
+                }
+                Some(_) => return None,
+                None => row.mnemonic.clone()?,
+            };
+            let (target, target_row) = moved(row.target?);
+            (!reaches(&mnemonic, row.address - saved[index], target)).then_some((index, target_row))
+        });
+        let Some((index, target_row)) = unreachable else {
+            return res;
+        };
+        let last = index.max(target_row.unwrap_or(0));
+        let given_up = match replaced.get(&index) {
+            Some(suggestion) => *suggestion,
+            None => match res.iter().rev().find(|suggestion| suggestion.bytes > 0 && suggestion.rows[0] < last) {
+                Some(suggestion) => *suggestion,
+                None => return res,
+            },
+        };
+        res.retain(|suggestion| !std::ptr::eq(*suggestion, given_up));
+    }
+}
+
+/// Applies the suggestions to the source of the analyzed file, keeping labels, comments and
+/// indentation. Suggestions in included files or macro expansions, and those that would
+/// take a jump out of reach when applied with the others, are left out.
+pub fn rewrite(contents: &str, rows: &[Row], suggestions: &[Suggestion]) -> String {
+    let mut lines = contents.lines().map(|line| Some(line.to_string())).collect::<Vec<_>>();
+    for suggestion in compatible(rows, suggestions) {
+        let replaced = suggestion.rows.iter().map(|&index| &rows[index]).collect::<Vec<_>>();
+        for (position, row) in replaced.iter().enumerate() {
+            let Some(Some(line)) = lines.get_mut(row.line - 1) else {
+                continue;
+            };
+            let (label, statement, comment) = split_statement(line);
+            let start = label.map_or(0, |_| line.find(':').unwrap() + 1);
+            let Some(at) = line[start..].find(statement).map(|at| start + at) else {
+                continue;
+            };
+            let end = at + statement.len();
+            let text = match (&suggestion.replacement, position) {
+                (Some(replacement), 0) => Some(format!("{}{}{}", &line[..at], replacement, &line[end..])),
+                _ if comment.is_some() => Some(format!("{}{}", &line[..at], line[end..].trim_start())),
+                _ => None,
+            };
+            lines[row.line - 1] = text;
+        }
+    }
+    let mut res = lines.into_iter().flatten().collect::<Vec<_>>().join("\n");
+    if contents.ends_with('\n') {
+        res.push('\n');
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::analyze;
+
+    #[test]
+    fn suggestions() {
+        let source = "START: MOV A, #0 ; clear\nADD A, #1\nMOV R0, A\nMOV A, R0\nLJMP NEAR\nNEAR: LCALL START\nLOOP: DEC R7\n  CJNE R7, #0, LOOP ; again\nRET\n";
+        let options = AnalysisOptions::default();
+        let rows = analyze(source, &options);
+        let suggestions = suggest(&rows, &options);
+        let found = suggestions
+            .iter()
+            .map(|suggestion| {
+                (
+                    rows[suggestion.rows[0]].line,
+                    suggestion.replacement.clone(),
+                    suggestion.bytes,
+                    suggestion.cycles,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (1, Some(String::from("CLR A")), 1, 0),
+                (2, Some(String::from("INC A")), 1, 0),
+                (4, None, 1, 1),
+                (5, Some(String::from("SJMP NEAR")), 1, 0),
+                (6, Some(String::from("ACALL START")), 1, 0),
+                (7, Some(String::from("DJNZ R7, LOOP")), 2, 1),
+            ],
+            found
+        );
+        assert_eq!(
+            "START: CLR A ; clear\nINC A\nMOV R0, A\nSJMP NEAR\nNEAR: ACALL START\nLOOP: DJNZ R7, LOOP\n  ; again\nRET\n",
+            rewrite(source, &rows, &suggestions)
+        );
+    }
+
+    #[test]
+    fn pages() {
+        // Shortening the LJMP would move T to 07FFH, out of the page of the AJMP after it.
+        let source = "LJMP X\nX: DS 7FDH\nT: NOP\nAJMP T\nMOV A, #0\n";
+        let options = AnalysisOptions::default();
+        let rows = analyze(source, &options);
+        let suggestions = suggest(&rows, &options);
+        let replacements = suggestions
+            .iter()
+            .map(|suggestion| suggestion.replacement.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Some(String::from("SJMP X")), Some(String::from("CLR A"))], replacements);
+        assert_eq!(
+            "LJMP X\nX: DS 7FDH\nT: NOP\nAJMP T\nCLR A\n",
+            rewrite(source, &rows, &suggestions)
+        );
+    }
+}
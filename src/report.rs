@@ -0,0 +1,859 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use crate::{
+    condition::{conditional, Conditional, Conditions},
+    dialect::Dialect,
+    encoding::{encode, resolve, resolve_bit},
+    get_cycle, get_memory, get_modes,
+    include::SourceLine,
+    macros::expand_macros,
+    instruction::{get_addr_mode_map, AddressingMode},
+    matching::{make_matcher, MatchError},
+    literal::{diagnose, LiteralDialect},
+    parser::{find_variant, get_all_inst_variants, get_dialect_regex, get_dialect_skip_list, ParseError},
+    sdcc::c_routines,
+    segment::{segment_directive, Declaration, Segments, Space},
+    symbols::{LabelCase, SymbolTable},
+};
+
+#[derive(Clone, Default)]
+pub struct AnalysisOptions {
+    pub dialect: Dialect,
+    pub label_case: LabelCase,
+    pub literals: LiteralDialect,
+    /// Directories searched for include files after the including file's own.
+    pub include_paths: Vec<PathBuf>,
+    /// Symbols defined before the first line, as with `-D DEBUG=1`.
+    pub defines: Vec<(String, i64)>,
+}
+
+pub struct Row {
+    /// The included file the line comes from, `None` for the analyzed file itself.
+    pub file: Option<PathBuf>,
+    /// How deeply the line is nested in includes, 0 for the analyzed file.
+    pub depth: usize,
+    /// How deeply the line is nested in macro expansions, 0 outside of them.
+    pub macro_depth: usize,
+    pub line: usize,
+    /// The address space `address` is in, set by segment directives.
+    pub space: Space,
+    pub address: usize,
+    pub label: Option<String>,
+    pub instruction: String,
+    /// The instruction or directive without its label and comment.
+    pub statement: String,
+    pub comment: Option<String>,
+    /// Mnemonic of the instruction on this line, `None` for directives and blank lines.
+    pub mnemonic: Option<String>,
+    pub operands: Vec<String>,
+    /// The symbol a `DATA`, `IDATA`, `XDATA`, `BIT` or `CODE` declaration on this line defines.
+    pub declaration: Option<Declaration>,
+    /// Resolved address of the label a jump or call on this line targets.
+    pub target: Option<usize>,
+    /// Locations reserved by a data directive such as `DB`, `DW` or `DS`, in bytes or, in
+    /// the bit space, bits.
+    pub data_bytes: usize,
+    pub modes: Result<Vec<AddressingMode>, ParseError>,
+    pub bytes: Result<usize, ParseError>,
+    pub cycles: Result<usize, MatchError>,
+    pub encoding: Option<Vec<u8>>,
+    /// A problem other than an invalid instruction, such as a missing include file.
+    pub problem: Option<String>,
+    /// Bytes and cycles of the expansion of a macro invocation, which follows its row.
+    pub expansion: Option<Totals>,
+    /// Set for lines in a conditional block that is not assembled.
+    pub inactive: bool,
+}
+
+impl Row {
+    pub fn has_error(&self) -> bool {
+        self.bytes.is_err() || self.cycles.is_err() || self.problem.is_some()
+    }
+
+    /// The instruction a generic `JMP` or `CALL` on this line was resolved to.
+    pub fn resolved(&self) -> Option<&str> {
+        let written = self.statement.split_whitespace().next()?;
+        self.mnemonic
+            .as_deref()
+            .filter(|mnemonic| !mnemonic.eq_ignore_ascii_case(written))
+    }
+}
+
+#[derive(Default)]
+pub struct Totals {
+    pub bytes: usize,
+    pub cycles: usize,
+}
+
+impl Totals {
+    pub fn of(rows: &[Row]) -> Self {
+        let mut res = Totals::default();
+        for row in rows {
+            res.bytes += row.bytes.clone().unwrap_or(0);
+            res.cycles += row.cycles.clone().unwrap_or(0);
+        }
+        res
+    }
+}
+
+#[derive(Default)]
+pub struct Statistics {
+    pub code_bytes: usize,
+    pub data_bytes: usize,
+    pub cycles: usize,
+    pub instructions: usize,
+    pub invalid: usize,
+    /// Number of instructions using each addressing mode.
+    pub modes: BTreeMap<String, usize>,
+    pub mnemonics: BTreeMap<String, usize>,
+    /// Locations used in each address space, in its unit.
+    pub usage: BTreeMap<Space, usize>,
+}
+
+impl Statistics {
+    pub fn of(rows: &[Row]) -> Self {
+        let totals = Totals::of(rows);
+        let mut res = Statistics {
+            code_bytes: totals.bytes,
+            cycles: totals.cycles,
+            ..Default::default()
+        };
+        res.usage.insert(Space::Code, totals.bytes);
+        for row in rows {
+            if row.space == Space::Code {
+                res.data_bytes += row.data_bytes;
+            }
+            *res.usage.entry(row.space).or_default() += row.data_bytes;
+            if row.has_error() {
+                res.invalid += 1;
+                continue;
+            }
+            let Some(mnemonic) = &row.mnemonic else {
+                continue;
+            };
+            res.instructions += 1;
+            *res.mnemonics.entry(mnemonic.clone()).or_default() += 1;
+            let mut modes = row
+                .modes
+                .iter()
+                .flatten()
+                .map(|mode| format!("{:?}", mode))
+                .collect::<Vec<_>>();
+            modes.sort();
+            modes.dedup();
+            for mode in modes {
+                *res.modes.entry(mode).or_default() += 1;
+            }
+        }
+        res
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoutineMode {
+    /// Every label starts a routine.
+    Labels,
+    /// Only labels targeted by `ACALL`/`LCALL` start a routine.
+    Calls,
+    /// The C functions of SDCC output, from its `function` banners.
+    CFunctions,
+    /// The C lines of SDCC output, from its `file.c:42:` markers.
+    CLines,
+}
+
+pub struct Routine {
+    pub name: String,
+    pub first_line: usize,
+    pub last_line: usize,
+    pub bytes: usize,
+    pub cycles: usize,
+}
+
+/// Groups rows into routines, each running from its label to the next routine's label.
+///
+/// Code before the first routine is grouped under `(start)`. SDCC output is grouped by
+/// [`c_routines`] instead.
+pub fn routines(rows: &[Row], mode: RoutineMode) -> Vec<Routine> {
+    if matches!(mode, RoutineMode::CFunctions | RoutineMode::CLines) {
+        return c_routines(rows, mode);
+    }
+    let targets = rows
+        .iter()
+        .filter(|row| matches!(row.mnemonic.as_deref(), Some("ACALL" | "LCALL")))
+        .filter_map(|row| row.target)
+        .collect::<Vec<_>>();
+    let mut res: Vec<Routine> = vec![];
+    for row in rows {
+        let starts = row.label.as_ref().filter(|_| match mode {
+            RoutineMode::Labels => true,
+            _ => targets.contains(&row.address),
+        });
+        if let Some(label) = starts {
+            res.push(Routine {
+                name: label.clone(),
+                first_line: row.line,
+                last_line: row.line,
+                bytes: 0,
+                cycles: 0,
+            });
+        } else if res.is_empty() {
+            res.push(Routine {
+                name: String::from("(start)"),
+                first_line: row.line,
+                last_line: row.line,
+                bytes: 0,
+                cycles: 0,
+            });
+        }
+        let routine = res.last_mut().unwrap();
+        routine.last_line = row.line;
+        routine.bytes += row.bytes.clone().unwrap_or(0);
+        routine.cycles += row.cycles.clone().unwrap_or(0);
+    }
+    res.retain(|routine| routine.name != "(start)" || routine.bytes > 0);
+    res
+}
+
+/// Execution time in seconds of `cycles` machine cycles, twelve oscillator periods each.
+pub fn cycle_time(cycles: usize, clock: f64) -> f64 {
+    cycles as f64 * 12.0 / clock
+}
+
+/// Parses an oscillator frequency such as `11.0592MHz`, `12000kHz` or `12` (MHz) into Hz.
+pub fn parse_clock(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let lower = text.to_ascii_lowercase();
+    let (number, scale) = if let Some(number) = lower.strip_suffix("mhz") {
+        (number, 1e6)
+    } else if let Some(number) = lower.strip_suffix("khz") {
+        (number, 1e3)
+    } else if let Some(number) = lower.strip_suffix("hz") {
+        (number, 1.0)
+    } else {
+        (lower.as_str(), 1e6)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(value * scale),
+        _ => Err(format!("invalid clock frequency `{}`", text)),
+    }
+}
+
+/// Splits a source line into its label, statement and comment.
+///
+/// A global SDCC label such as `_main::` loses both colons.
+pub fn split_statement(raw_line: &str) -> (Option<&str>, &str, Option<&str>) {
+    let (code, comment) = match raw_line.split_once(';') {
+        Some((code, comment)) => (code, Some(comment.trim())),
+        None => (raw_line, None),
+    };
+    match code.split_once(':') {
+        Some((label, statement)) => (
+            Some(label.trim()),
+            statement.trim_start_matches(':').trim(),
+            comment,
+        ),
+        None => (None, code.trim(), comment),
+    }
+}
+
+fn data_items(args: &str) -> Vec<&str> {
+    let mut res = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (index, c) in args.char_indices() {
+        match c {
+            '\'' | '"' => quoted = !quoted,
+            ',' if !quoted => {
+                res.push(args[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(args[start..].trim());
+    res
+}
+
+/// Returns the arguments of `statement` when it is the directive `name`, in any case.
+pub fn directive_args<'a>(statement: &'a str, name: &str) -> Option<&'a str> {
+    let (directive, args) = statement.split_once(' ').unwrap_or((statement, ""));
+    directive.eq_ignore_ascii_case(name).then(|| args.trim())
+}
+
+fn data_bytes(args: &str, symbols: &SymbolTable) -> Option<Vec<u8>> {
+    let mut res = vec![];
+    for item in data_items(args) {
+        if item.len() >= 2 && (item.starts_with('\'') || item.starts_with('"')) {
+            res.extend(item[1..item.len() - 1].bytes());
+        } else {
+            res.push(resolve(item, symbols).filter(|v| (-128..=255).contains(v))? as u8);
+        }
+    }
+    Some(res)
+}
+
+fn data_words(args: &str, symbols: &SymbolTable) -> Option<Vec<u8>> {
+    let mut res = vec![];
+    for item in data_items(args) {
+        let value = resolve(item, symbols).filter(|v| (-0x8000..=0xFFFF).contains(v))?;
+        res.extend([(value >> 8) as u8, value as u8]);
+    }
+    Some(res)
+}
+
+fn data_length(statement: &str, symbols: &SymbolTable) -> Option<usize> {
+    if let Some(args) = directive_args(statement, "DW").filter(|args| !args.is_empty()) {
+        return Some(data_items(args).len() * 2);
+    }
+    if let Some(args) = directive_args(statement, "DS").or_else(|| directive_args(statement, "DBIT")) {
+        return resolve(args, symbols).map(|length| length.max(0) as usize);
+    }
+    let args = directive_args(statement, "DB").filter(|args| !args.is_empty())?;
+    Some(
+        data_items(args)
+            .into_iter()
+            .map(|item| match item.starts_with(['\'', '"']) {
+                true => item.len().saturating_sub(2),
+                false => 1,
+            })
+            .sum(),
+    )
+}
+
+fn encode_statement(
+    statement: &str,
+    address: usize,
+    symbols: &SymbolTable,
+    all_inst: &HashMap<String, Vec<Vec<String>>>,
+    regex_map: &HashMap<String, regex::Regex>,
+) -> Option<Vec<u8>> {
+    if let Some(args) = directive_args(statement, "DB") {
+        return data_bytes(args, symbols);
+    }
+    if let Some(args) = directive_args(statement, "DW") {
+        return data_words(args, symbols);
+    }
+    if directive_args(statement, "DS").is_some() || directive_args(statement, "DBIT").is_some() {
+        return None;
+    }
+    let (instruction, raw_operands) = statement.split_once(' ').unwrap_or((statement, ""));
+    let kinds = find_variant(instruction, raw_operands, all_inst, regex_map)?;
+    let operands = raw_operands.split(',').map(str::trim).collect::<Vec<_>>();
+    encode(instruction, kinds, &operands, address, symbols)
+}
+
+/// Explains an operand that is neither a number nor a known name but looks like a mistyped
+/// literal, such as `FFH` for `0FFH`, which the operand patterns accept as a symbol.
+fn literal_problem(raw_operands: &str, symbols: &SymbolTable) -> Option<String> {
+    raw_operands.split(',').map(str::trim).find_map(|operand| {
+        let operand = operand.trim_start_matches('#');
+        let operand = operand.split_once('.').map_or(operand, |(byte, _)| byte);
+        resolve(operand, symbols).is_none().then(|| diagnose(operand)).flatten()
+    })
+}
+
+/// Resolves the code address a jump or call statement transfers control to.
+fn branch_target(
+    statement: &str,
+    symbols: &SymbolTable,
+    all_inst: &HashMap<String, Vec<Vec<String>>>,
+    regex_map: &HashMap<String, regex::Regex>,
+) -> Option<usize> {
+    let (instruction, raw_operands) = statement.split_once(' ').unwrap_or((statement, ""));
+    let kinds = find_variant(instruction, raw_operands, all_inst, regex_map)?;
+    let index = kinds
+        .iter()
+        .position(|kind| matches!(kind.as_str(), "rel1B" | "addr11" | "addr2B"))?;
+    let operand = raw_operands.split(',').nth(index)?.trim();
+    resolve(operand, symbols).map(|value| value as usize)
+}
+
+/// Forms of a generic `JMP` and `CALL`, from the shortest.
+const JUMPS: [&str; 3] = ["SJMP", "AJMP", "LJMP"];
+const CALLS: [&str; 2] = ["ACALL", "LCALL"];
+
+/// The forms a generic jump or call can take and its target, `None` for other statements
+/// including `JMP @A+DPTR`.
+fn generic(statement: &str) -> Option<(&'static [&'static str], &str)> {
+    let (instruction, operand) = statement.split_once(' ')?;
+    let operand = operand.trim();
+    if operand.contains([',', '@']) {
+        return None;
+    }
+    match instruction.to_ascii_uppercase().as_str() {
+        "JMP" => Some((&JUMPS, operand)),
+        "CALL" => Some((&CALLS, operand)),
+        _ => None,
+    }
+}
+
+/// The statement with a generic jump or call replaced by its form at index `form`.
+fn concrete(statement: &str, form: usize) -> String {
+    match generic(statement) {
+        Some((sizes, operand)) => format!("{} {}", sizes[form], operand),
+        None => statement.to_string(),
+    }
+}
+
+/// Whether `mnemonic` at `address` can transfer control to `target`.
+pub fn reaches(mnemonic: &str, address: usize, target: usize) -> bool {
+    let next = address + 2;
+    match mnemonic {
+        "SJMP" => (-128..=127).contains(&(target as i64 - next as i64)),
+        "AJMP" | "ACALL" => next & 0xF800 == target & 0xF800,
+        _ => true,
+    }
+}
+
+pub fn analyze(contents: &str, options: &AnalysisOptions) -> Vec<Row> {
+    analyze_source(&SourceLine::plain(contents), options)
+}
+
+/// The label and statement of a line the analysis looks at. Macro definitions are not
+/// analyzed and an invocation only defines its label, its expansion holds the code.
+fn analyzed_parts(line: &SourceLine, dialect: Dialect) -> (Option<&str>, String) {
+    let (label, statement, _) = split_statement(&line.text);
+    match (line.skipped, line.invocation) {
+        (true, _) => (None, String::new()),
+        (_, true) => (label, String::new()),
+        _ => (label, dialect.canonical(statement)),
+    }
+}
+
+/// Analyzes a source whose includes were already expanded, expanding its macros first.
+pub fn analyze_source(source: &[SourceLine], options: &AnalysisOptions) -> Vec<Row> {
+    let source = expand_macros(source);
+    let dialect = options.dialect;
+    let all_inst_map = get_all_inst_variants();
+    let regex_map = get_dialect_regex(dialect, options.literals);
+    let addr_map_mode = get_addr_mode_map();
+    let skip_list = get_dialect_skip_list(dialect);
+    let matcher = make_matcher();
+
+    let mut symbols = SymbolTable::new(options.label_case);
+    for (name, value) in &options.defines {
+        symbols.insert(name, *value);
+    }
+    // Generic jumps and calls start in their shortest form and grow until every target is
+    // in reach. Forms only ever grow, so this ends after a few passes. Each pass marks the
+    // lines it skips on its own copy of the source, so conditionals are evaluated anew.
+    let mut forms = vec![0; source.len()];
+    let (source, symbols, addresses, inactive, declarations) = loop {
+        let mut pass = source.clone();
+        let mut symbols = symbols.clone();
+        let mut addresses = vec![];
+        let mut segments = Segments::default();
+        let mut conditions = Conditions::default();
+        let mut open = vec![];
+        let mut inactive = vec![false; source.len()];
+        let mut declarations = vec![None; source.len()];
+        for index in 0..source.len() {
+            let (_, statement) = analyzed_parts(&pass[index], dialect);
+            let directive = conditional(&statement);
+            let is_directive = directive.is_some();
+            if let Some(directive) = directive {
+                let shown = match directive {
+                    Conditional::If(_) | Conditional::IfDef(_) | Conditional::IfNDef(_) => {
+                        open.push(index);
+                        conditions.active()
+                    }
+                    Conditional::EndIf => {
+                        open.pop();
+                        conditions.outer()
+                    }
+                    _ => conditions.outer(),
+                };
+                if let Err(problem) = conditions.apply(directive, &symbols) {
+                    pass[index].problem.get_or_insert(problem);
+                }
+                inactive[index] = !shown;
+            } else {
+                inactive[index] = !conditions.active();
+            }
+            if inactive[index] || is_directive {
+                pass[index].skipped = true;
+                addresses.push(segments.location());
+                continue;
+            }
+            let line = &pass[index];
+            let (label, statement) = analyzed_parts(line, dialect);
+            let statement = concrete(&statement, forms[index]);
+            if let Some(directive) = segment_directive(&statement, dialect) {
+                segments.apply(directive, &symbols);
+            }
+            if let Some(origin) = directive_args(&statement, "ORG") {
+                if let Some(origin) = resolve(origin, &symbols) {
+                    segments.set_address(origin as usize);
+                }
+            }
+            if let Some(label) = label {
+                symbols.insert(label, segments.location().1 as i64);
+            }
+            let tokens = statement.splitn(3, ' ').collect::<Vec<_>>();
+            if let [name, directive, value] = tokens[..] {
+                let value = value.trim();
+                let value = match directive.to_ascii_uppercase().as_str() {
+                    "BIT" => resolve_bit(value, &symbols),
+                    directive if dialect.symbol_directives().contains(&directive) => {
+                        resolve(value, &symbols)
+                    }
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    symbols.insert(name, value);
+                    declarations[index] = Space::of_declaration(directive).map(|space| Declaration {
+                        name: name.to_string(),
+                        space,
+                        address: value as usize,
+                    });
+                }
+            }
+            addresses.push(segments.location());
+            segments.advance(data_length(&statement, &symbols).unwrap_or_else(|| {
+                get_memory(&statement, &all_inst_map, &regex_map, &addr_map_mode, &skip_list)
+                    .unwrap_or(0)
+            }));
+        }
+        for index in open {
+            pass[index]
+                .problem
+                .get_or_insert_with(|| String::from("IF without ENDIF"));
+        }
+        let mut grown = false;
+        for index in (0..source.len()).filter(|&index| !inactive[index]) {
+            let (_, statement) = analyzed_parts(&pass[index], dialect);
+            let Some((sizes, operand)) = generic(&statement) else {
+                continue;
+            };
+            let address = addresses[index].1;
+            let target = resolve(operand, &symbols).map(|target| target as usize);
+            let fits = (forms[index]..sizes.len())
+                .find(|&form| target.is_some_and(|target| reaches(sizes[form], address, target)))
+                .unwrap_or(sizes.len() - 1);
+            if fits != forms[index] {
+                forms[index] = fits;
+                grown = true;
+            }
+        }
+        if !grown {
+            break (pass, symbols, addresses, inactive, declarations);
+        }
+    };
+
+    let mut res = vec![];
+    let lines = source.iter().zip(addresses).zip(inactive).zip(declarations).zip(forms);
+    for ((((source, (space, address)), inactive), declaration), form) in lines {
+        let line = source.text.as_str();
+        let (label, original, comment) = split_statement(line);
+        let (_, statement) = analyzed_parts(source, dialect);
+        let original = if statement.is_empty() { "" } else { original };
+        let statement = concrete(&statement, form);
+        let statement = statement.as_str();
+        let bytes = get_memory(statement, &all_inst_map, &regex_map, &addr_map_mode, &skip_list);
+        let (mnemonic, raw_operands) = statement.split_once(' ').unwrap_or((statement, ""));
+        let mnemonic = Some(mnemonic.to_ascii_uppercase())
+            .filter(|mnemonic| all_inst_map.contains_key(mnemonic));
+        let problem = source.problem.clone().or_else(|| match (&mnemonic, inactive) {
+            (Some(_), false) => literal_problem(raw_operands, &symbols),
+            _ => None,
+        });
+        res.push(Row {
+            file: source.file.clone(),
+            depth: source.depth,
+            macro_depth: source.macro_depth,
+            line: source.line,
+            space,
+            address,
+            label: label.map(String::from),
+            instruction: if line.contains(';') {
+                line.split_once(';').unwrap().0.trim().to_string()
+            } else {
+                line.to_string()
+            },
+            statement: original.to_string(),
+            comment: comment.map(String::from),
+            declaration,
+            target: mnemonic
+                .as_ref()
+                .and_then(|_| branch_target(statement, &symbols, &all_inst_map, &regex_map)),
+            mnemonic,
+            operands: raw_operands
+                .split(',')
+                .map(str::trim)
+                .filter(|op| !op.is_empty())
+                .map(String::from)
+                .collect(),
+            data_bytes: data_length(statement, &symbols).unwrap_or(0),
+            modes: get_modes(statement, &all_inst_map, &regex_map, &addr_map_mode, &skip_list),
+            cycles: get_cycle(statement, &matcher, &all_inst_map, &regex_map, &skip_list),
+            encoding: match &bytes {
+                Ok(_) if !statement.is_empty() => {
+                    encode_statement(statement, address, &symbols, &all_inst_map, &regex_map)
+                }
+                _ => None,
+            },
+            bytes,
+            problem,
+            expansion: None,
+            inactive,
+        });
+    }
+    for (index, invocation) in source.iter().enumerate().filter(|(_, line)| line.invocation) {
+        let start = index + 1;
+        let length = res[start..]
+            .iter()
+            .take_while(|row| row.macro_depth > invocation.macro_depth)
+            .count();
+        res[index].expansion = Some(Totals::of(&res[start..start + length]));
+    }
+    res
+}
+
+/// Folds the rows of included files into the include line of the analyzed file, which
+/// then carries their bytes and cycles.
+pub fn collapse_includes(rows: Vec<Row>) -> Vec<Row> {
+    let mut res: Vec<Row> = vec![];
+    for row in rows {
+        match res.last_mut() {
+            Some(include) if row.depth > 0 => {
+                if let (Ok(total), Ok(bytes)) = (&mut include.bytes, &row.bytes) {
+                    *total += bytes;
+                }
+                if let (Ok(total), Ok(cycles)) = (&mut include.cycles, &row.cycles) {
+                    *total += cycles;
+                }
+                include.data_bytes += row.data_bytes;
+                if row.has_error() && include.problem.is_none() {
+                    include.problem = Some(String::from("the included file has errors"));
+                }
+            }
+            _ => res.push(row),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_and_encoding() {
+        let rows = analyze(
+            "ORG 100H\nWAIT: JNB TI, WAIT\nDB 'OK', 0\nSJMP WAIT",
+            &AnalysisOptions::default(),
+        );
+        let addresses = rows.iter().map(|row| row.address).collect::<Vec<_>>();
+        assert_eq!(vec![0x100, 0x100, 0x103, 0x106], addresses);
+        assert_eq!(Some(vec![0x4F, 0x4B, 0x00]), rows[2].encoding);
+        assert_eq!(Some(vec![0x80, 0xF8]), rows[3].encoding);
+    }
+
+    #[test]
+    fn statistics() {
+        let rows = analyze(
+            "MOV A, #5\nMOV R0, A\nADD A, #1\nFOO A\nDB 1, 2",
+            &AnalysisOptions::default(),
+        );
+        let stats = Statistics::of(&rows);
+        assert_eq!(3, stats.instructions);
+        assert_eq!(1, stats.invalid);
+        assert_eq!(2, stats.data_bytes);
+        assert_eq!(Some(&2), stats.modes.get("Immediate"));
+        assert_eq!(Some(&2), stats.mnemonics.get("MOV"));
+    }
+
+    #[test]
+    fn call_routines() {
+        let rows = analyze(
+            "MAIN: ACALL DELAY\nHERE: SJMP HERE\nDELAY: MOV R7, #10\nBACK: DJNZ R7, BACK\nRET",
+            &AnalysisOptions::default(),
+        );
+        let names = |mode| {
+            routines(&rows, mode)
+                .into_iter()
+                .map(|routine| (routine.name, routine.bytes, routine.cycles))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(String::from("(start)"), 4, 4), (String::from("DELAY"), 5, 5)],
+            names(RoutineMode::Calls)
+        );
+        assert_eq!(4, names(RoutineMode::Labels).len());
+    }
+
+    #[test]
+    fn mixed_case() {
+        let source = "Loop: mov a, #0fh\nMov A,r0\nsjmp LOOP";
+        let rows = analyze(source, &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!("Loop: mov a, #0fh", rows[0].instruction);
+        assert_eq!(Some(vec![0x80, 0xFB]), rows[2].encoding);
+
+        let options = AnalysisOptions {
+            label_case: LabelCase::Sensitive,
+            ..Default::default()
+        };
+        let rows = analyze(source, &options);
+        assert_eq!(None, rows[2].encoding);
+    }
+
+    #[test]
+    fn literal_dialects() {
+        let source = "MOV A, #0x1F\nMOV A, #1FH\nMOV A, #'A'";
+        let rows = analyze(source, &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(Some(vec![0x74, 0x41]), rows[2].encoding);
+
+        let options = AnalysisOptions {
+            literals: LiteralDialect::Intel,
+            ..Default::default()
+        };
+        let rows = analyze(source, &options);
+        assert!(rows[0].has_error());
+        assert!(!rows[1].has_error());
+    }
+
+    #[test]
+    fn literal_hints() {
+        let rows = analyze("MOV A, #FFH\nMOV A, #0FFH", &AnalysisOptions::default());
+        let hint = String::from("hex literals must start with a digit, write `0FFH`");
+        assert_eq!(Some(hint), rows[0].problem);
+        assert!(rows[0].has_error());
+        assert!(!rows[1].has_error());
+
+        // Without a leading zero the operand is a symbol, an error unless it is defined.
+        let rows = analyze("MOV A, FFH\nMOV FFH, A\nSETB FFH.1", &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| row.has_error() && row.encoding.is_none()));
+        let rows = analyze("FFH EQU 30H\nMOV A, #FFH\nMOV A, FFH", &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(Some(vec![0xE5, 0x30]), rows[2].encoding);
+    }
+
+    #[test]
+    fn assembler_dialects() {
+        let sdcc = "\t.area CSEG (CODE)\n_count = 0x30\n_main::\n\tmov\t_count,#<_msg\n\tpush\tar7\n00101$:\n\tsjmp\t00101$\n_msg:\n\t.ascii \"hi\"";
+        let options = AnalysisOptions {
+            dialect: Dialect::Sdcc,
+            literals: Dialect::Sdcc.literals(),
+            ..Default::default()
+        };
+        let rows = analyze(sdcc, &options);
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(Some(String::from("_main")), rows[2].label);
+        assert_eq!(Some(vec![0x75, 0x30, 0x07]), rows[3].encoding);
+        assert_eq!(Some(vec![0xC0, 0x07]), rows[4].encoding);
+        assert_eq!(Some(vec![0x80, 0xFE]), rows[6].encoding);
+        assert_eq!(2, rows[8].data_bytes);
+
+        let a51 = "$NOMOD51\nLED BIT P1.0\nCOUNT DATA 30H\nCPL LED\nINC COUNT\nDW 1234H\nDS 2\nRET";
+        let rows = analyze(a51, &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(Some(vec![0xB2, 0x90]), rows[3].encoding);
+        assert_eq!(Some(vec![0x05, 0x30]), rows[4].encoding);
+        assert_eq!(Some(vec![0x12, 0x34]), rows[5].encoding);
+        assert_eq!(8, rows[7].address);
+    }
+
+    #[test]
+    fn segments() {
+        let source = "VARS SEGMENT DATA\nRSEG VARS\nCOUNT: DS 2\nBSEG AT 0\nFLAG: DBIT 1\nCSEG AT 0\nMOV COUNT, #1\nSETB FLAG\nRSEG VARS\nTOTAL: DS 1\nXSEG AT 100H\nBUFFER: DS 64";
+        let rows = analyze(source, &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(Some(vec![0x75, 0x00, 0x01]), rows[6].encoding);
+        assert_eq!(Some(vec![0xD2, 0x00]), rows[7].encoding);
+        assert_eq!((Space::Data, 2), (rows[9].space, rows[9].address));
+        assert_eq!((Space::Xdata, 0x100), (rows[11].space, rows[11].address));
+
+        let stats = Statistics::of(&rows);
+        assert_eq!(0, stats.data_bytes);
+        let usage = stats.usage.into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            vec![(Space::Code, 5), (Space::Data, 3), (Space::Bit, 1), (Space::Xdata, 64)],
+            usage
+        );
+    }
+
+    #[test]
+    fn relaxation() {
+        let source = "START: JMP NEAR\nCALL FAR\nNEAR: jmp START\nJMP @A+DPTR\nORG 800H\nFAR: CALL START\nJMP FAR\nJMP OUTSIDE";
+        let rows = analyze(source, &AnalysisOptions::default());
+        let resolved = rows.iter().map(|row| row.resolved()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Some("SJMP"),
+                Some("LCALL"),
+                Some("SJMP"),
+                None,
+                None,
+                Some("LCALL"),
+                Some("SJMP"),
+                Some("LJMP"),
+            ],
+            resolved
+        );
+        assert_eq!(Some(vec![0x80, 0x03]), rows[0].encoding);
+        assert_eq!(Some(vec![0x12, 0x08, 0x00]), rows[1].encoding);
+        assert_eq!(Some(vec![0x80, 0xF9]), rows[2].encoding);
+        assert_eq!(Some(vec![0x73]), rows[3].encoding);
+        assert_eq!(Some(String::from("JMP")), rows[3].mnemonic);
+        assert_eq!(0x805, rows[7].address);
+
+        // Growing a jump analyzes the source again, with its conditional blocks.
+        let source = "IF 0\nMOV A, #1\nENDIF\nJMP FAR\nORG 900H\nFAR: NOP";
+        let rows = analyze(source, &AnalysisOptions::default());
+        assert_eq!(Some("LJMP"), rows[3].resolved());
+        assert!(rows[1].inactive);
+        assert_eq!(None, rows[1].encoding);
+        assert_eq!(4, Totals::of(&rows).bytes);
+    }
+
+    #[test]
+    fn macro_rollup() {
+        let source = "SAVE MACRO\nPUSH ACC\nPUSH PSW\nENDM\nMAIN: SAVE\nSJMP MAIN";
+        let rows = analyze(source, &AnalysisOptions::default());
+        assert!(rows.iter().all(|row| !row.has_error()));
+        assert_eq!(4, rows[4].expansion.as_ref().unwrap().bytes);
+        assert_eq!(Some(vec![0xC0, 0xE0]), rows[5].encoding);
+        assert_eq!(Some(vec![0x80, 0xFA]), rows[7].encoding);
+        assert_eq!(6, Totals::of(&rows).bytes);
+    }
+
+    #[test]
+    fn conditional_assembly() {
+        let source = "IF DEBUG\nLABEL: MOV A, #1\nELSE\nNOP\nNOP\nENDIF\nSJMP LABEL\nIF MISSING\nENDIF\nIF 1";
+        let rows = analyze(source, &AnalysisOptions::default());
+        assert_eq!(Some(String::from("undefined symbol `DEBUG`")), rows[0].problem);
+
+        let options = AnalysisOptions {
+            defines: vec![(String::from("DEBUG"), 0)],
+            ..Default::default()
+        };
+        let rows = analyze(source, &options);
+        let inactive = rows.iter().map(|row| row.inactive).collect::<Vec<_>>();
+        let expected = [false, true, false, false, false, false, false, false, false, false];
+        assert_eq!(expected.to_vec(), inactive);
+        // The two NOPs and the SJMP, sized although its label is in the inactive block.
+        assert_eq!(4, Totals::of(&rows).bytes);
+        assert_eq!(None, rows[6].encoding);
+        assert!(rows[7].problem.is_some());
+        assert_eq!(Some(String::from("IF without ENDIF")), rows[9].problem);
+    }
+
+    #[test]
+    fn collapsed_includes() {
+        let mut source = SourceLine::plain("INCLUDE lib.inc\nINIT: MOV A, #1\nRET\nACALL INIT");
+        for line in &mut source[1..3] {
+            line.depth = 1;
+            line.file = Some(PathBuf::from("lib.inc"));
+        }
+        let rows = collapse_includes(analyze_source(&source, &AnalysisOptions::default()));
+        assert_eq!(2, rows.len());
+        assert_eq!(Totals::of(&rows).bytes, 5);
+        assert_eq!(Ok(3), rows[0].bytes);
+        assert_eq!(Some(vec![0x11, 0x00]), rows[1].encoding);
+    }
+}
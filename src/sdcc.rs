@@ -0,0 +1,126 @@
+use crate::report::{Routine, RoutineMode, Row};
+
+/// A C statement SDCC annotates the generated code with, e.g. `; main.c:42: x = y;`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CLine {
+    pub file: String,
+    pub line: usize,
+    pub text: String,
+}
+
+/// Parses the comment of a C line marker.
+pub fn c_line(comment: &str) -> Option<CLine> {
+    let (file, rest) = comment.trim().split_once(':')?;
+    let (line, text) = rest.split_once(':')?;
+    if !file.ends_with(".c") && !file.ends_with(".h") || file.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(CLine {
+        file: file.to_string(),
+        line: line.parse().ok()?,
+        text: text.trim().to_string(),
+    })
+}
+
+/// Parses the comment of the `; function main` banner SDCC writes above each function.
+pub fn c_function(comment: &str) -> Option<&str> {
+    comment
+        .trim()
+        .strip_prefix("function ")
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+
+/// Attributes the bytes and cycles of SDCC output to the C functions or C lines they were
+/// generated from.
+///
+/// A C line may be split over several places, such as the test and the step of a `for`
+/// loop, so rows are summed per C line rather than per contiguous block.
+pub fn c_routines(rows: &[Row], mode: RoutineMode) -> Vec<Routine> {
+    let mut res: Vec<Routine> = vec![];
+    let mut current = None;
+    for row in rows {
+        if let Some(comment) = &row.comment {
+            let name = match mode {
+                RoutineMode::CLines => c_line(comment)
+                    .map(|line| format!("{}:{} {}", line.file, line.line, line.text)),
+                _ => c_function(comment).map(String::from),
+            };
+            if name.is_some() {
+                current = name;
+            }
+        }
+        let Some(name) = &current else {
+            continue;
+        };
+        let index = match res.iter().position(|routine| routine.name == *name) {
+            Some(index) => index,
+            None => {
+                res.push(Routine {
+                    name: name.clone(),
+                    first_line: row.line,
+                    last_line: row.line,
+                    bytes: 0,
+                    cycles: 0,
+                });
+                res.len() - 1
+            }
+        };
+        let routine = &mut res[index];
+        routine.last_line = row.line;
+        routine.bytes += row.bytes.clone().unwrap_or(0);
+        routine.cycles += row.cycles.clone().unwrap_or(0);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dialect::Dialect,
+        report::{analyze, AnalysisOptions},
+    };
+
+    const SOURCE: &str = ";\t-----------------------------------------
+;\t function main
+;\t-----------------------------------------
+_main:
+;\tmain.c:4: for (i = 0; i < 10; i++)
+\tmov\tr7,#0x00
+00102$:
+;\tmain.c:5: P1 = i;
+\tmov\t_P1,r7
+;\tmain.c:4: for (i = 0; i < 10; i++)
+\tinc\tr7
+\tcjne\tr7,#0x0a,00102$
+;\tmain.c:6: }
+\tret";
+
+    #[test]
+    fn attribution() {
+        let options = AnalysisOptions {
+            dialect: Dialect::Sdcc,
+            literals: Dialect::Sdcc.literals(),
+            ..Default::default()
+        };
+        let rows = analyze(SOURCE, &options);
+        assert!(rows.iter().all(|row| !row.has_error()));
+        let costs = |mode| {
+            c_routines(&rows, mode)
+                .into_iter()
+                .map(|routine| (routine.name, routine.bytes, routine.cycles))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![(String::from("main"), 9, 8)], costs(RoutineMode::CFunctions));
+        assert_eq!(
+            vec![
+                (String::from("main.c:4 for (i = 0; i < 10; i++)"), 6, 4),
+                (String::from("main.c:5 P1 = i;"), 2, 2),
+                (String::from("main.c:6 }"), 1, 2),
+            ],
+            costs(RoutineMode::CLines)
+        );
+        assert_eq!(None, c_line("main: loop"));
+    }
+}
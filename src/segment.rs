@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::{dialect::Dialect, encoding::resolve, symbols::SymbolTable};
+
+/// An 8051 address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Space {
+    #[default]
+    Code,
+    /// Directly addressable internal RAM.
+    Data,
+    /// Indirectly addressable internal RAM.
+    Idata,
+    Bit,
+    /// External RAM.
+    Xdata,
+}
+
+impl Space {
+    pub const ALL: [Space; 5] = [Space::Code, Space::Data, Space::Idata, Space::Bit, Space::Xdata];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Space::Code => "code",
+            Space::Data => "data",
+            Space::Idata => "idata",
+            Space::Bit => "bit",
+            Space::Xdata => "xdata",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Space::ALL
+            .into_iter()
+            .find(|space| space.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The space a symbol declared with `DATA`, `IDATA`, `XDATA`, `BIT` or `CODE` is in.
+    pub fn of_declaration(directive: &str) -> Option<Self> {
+        match directive.to_ascii_uppercase().as_str() {
+            "CODE" => Some(Space::Code),
+            "DATA" => Some(Space::Data),
+            "IDATA" => Some(Space::Idata),
+            "BIT" => Some(Space::Bit),
+            "XDATA" => Some(Space::Xdata),
+            _ => None,
+        }
+    }
+
+    /// The unit locations of the space are counted in.
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Space::Bit => "bits",
+            _ => "bytes",
+        }
+    }
+
+    /// Name of the A51 absolute segment of the space, `CSEG` for code.
+    fn absolute_segment(&self) -> &'static str {
+        match self {
+            Space::Code => "CSEG",
+            Space::Data => "DSEG",
+            Space::Idata => "ISEG",
+            Space::Bit => "BSEG",
+            Space::Xdata => "XSEG",
+        }
+    }
+}
+
+/// A symbol given an address in a space, as with `COUNT DATA 30H`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: String,
+    pub space: Space,
+    pub address: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SegmentDirective<'a> {
+    /// `CSEG AT 100H`, `DSEG` and friends.
+    Absolute { space: Space, at: Option<&'a str> },
+    /// `NAME SEGMENT CODE`.
+    Declare { name: &'a str, space: Space },
+    /// `RSEG NAME`.
+    Select(&'a str),
+    /// SDCC's `.area DSEG (DATA)`.
+    Area { name: &'a str, space: Space },
+}
+
+/// Address space of an SDCC area, from its attributes or else its conventional name.
+fn area_space(name: &str, attributes: &str) -> Space {
+    let name = name.to_ascii_uppercase();
+    let attributes = attributes.to_ascii_uppercase();
+    let has = |attribute: &str| {
+        attributes
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|part| part == attribute)
+    };
+    match name.as_str() {
+        "ISEG" | "SSEG" => Space::Idata,
+        "BSEG" => Space::Bit,
+        _ if has("XDATA") => Space::Xdata,
+        _ if has("BIT") => Space::Bit,
+        _ if has("DATA") => Space::Data,
+        "DSEG" | "OSEG" | "RSEG" => Space::Data,
+        "XSEG" | "PSEG" | "XISEG" => Space::Xdata,
+        _ => Space::Code,
+    }
+}
+
+pub fn segment_directive(statement: &str, dialect: Dialect) -> Option<SegmentDirective<'_>> {
+    let mut tokens = statement.splitn(3, char::is_whitespace);
+    let first = tokens.next()?;
+    let second = tokens.next().unwrap_or("");
+    let rest = tokens.next().unwrap_or("").trim();
+    if dialect == Dialect::Sdcc {
+        let args = first
+            .eq_ignore_ascii_case(".area")
+            .then(|| statement[first.len()..].trim())?;
+        let (name, attributes) = args.split_once('(').unwrap_or((args, ""));
+        let name = name.trim();
+        return (!name.is_empty()).then(|| SegmentDirective::Area {
+            name,
+            space: area_space(name, attributes),
+        });
+    }
+    if let Some(space) = Space::ALL
+        .into_iter()
+        .find(|space| first.eq_ignore_ascii_case(space.absolute_segment()))
+    {
+        let at = second
+            .eq_ignore_ascii_case("AT")
+            .then_some(rest)
+            .filter(|at| !at.is_empty());
+        return Some(SegmentDirective::Absolute { space, at });
+    }
+    if first.eq_ignore_ascii_case("RSEG") && !second.is_empty() {
+        return Some(SegmentDirective::Select(second));
+    }
+    if second.eq_ignore_ascii_case("SEGMENT") {
+        let space = rest.split_whitespace().next().and_then(Space::from_name)?;
+        return Some(SegmentDirective::Declare { name: first, space });
+    }
+    None
+}
+
+/// The location counters of all segments and the one currently assembled into.
+pub struct Segments {
+    counters: HashMap<String, (Space, usize)>,
+    current: String,
+}
+
+impl Default for Segments {
+    fn default() -> Self {
+        let current = String::from(Space::Code.absolute_segment());
+        Segments {
+            counters: HashMap::from([(current.clone(), (Space::Code, 0))]),
+            current,
+        }
+    }
+}
+
+impl Segments {
+    /// The space and address of the current location.
+    pub fn location(&self) -> (Space, usize) {
+        self.counters[&self.current]
+    }
+
+    pub fn set_address(&mut self, address: usize) {
+        self.counters.get_mut(&self.current).unwrap().1 = address;
+    }
+
+    pub fn advance(&mut self, length: usize) {
+        self.counters.get_mut(&self.current).unwrap().1 += length;
+    }
+
+    fn enter(&mut self, name: &str, space: Space) {
+        let key = name.to_ascii_uppercase();
+        self.counters.entry(key.clone()).or_insert((space, 0));
+        self.current = key;
+    }
+
+    pub fn apply(&mut self, directive: SegmentDirective, symbols: &SymbolTable) {
+        match directive {
+            SegmentDirective::Absolute { space, at } => {
+                self.enter(space.absolute_segment(), space);
+                if let Some(address) = at.and_then(|at| resolve(at, symbols)) {
+                    self.set_address(address as usize);
+                }
+            }
+            SegmentDirective::Declare { name, space } => {
+                self.counters
+                    .entry(name.to_ascii_uppercase())
+                    .or_insert((space, 0));
+            }
+            SegmentDirective::Select(name) => self.enter(name, Space::Code),
+            SegmentDirective::Area { name, space } => self.enter(name, space),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        assert_eq!(
+            Some(SegmentDirective::Absolute {
+                space: Space::Data,
+                at: Some("30H")
+            }),
+            segment_directive("DSEG AT 30H", Dialect::A51)
+        );
+        assert_eq!(
+            Some(SegmentDirective::Area {
+                name: "XSEG",
+                space: Space::Xdata
+            }),
+            segment_directive(".area XSEG (XDATA)", Dialect::Sdcc)
+        );
+        assert_eq!(
+            Some(SegmentDirective::Area {
+                name: "SSEG",
+                space: Space::Idata
+            }),
+            segment_directive(".area SSEG", Dialect::Sdcc)
+        );
+
+        let symbols = SymbolTable::default();
+        let mut segments = Segments::default();
+        segments.apply(segment_directive("VARS SEGMENT DATA", Dialect::A51).unwrap(), &symbols);
+        segments.apply(segment_directive("RSEG VARS", Dialect::A51).unwrap(), &symbols);
+        segments.advance(2);
+        assert_eq!((Space::Data, 2), segments.location());
+        segments.apply(segment_directive("CSEG", Dialect::A51).unwrap(), &symbols);
+        assert_eq!((Space::Code, 0), segments.location());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    cfg::{Cfg, EdgeKind},
+    literal::parse_number,
+    report::Row,
+};
+
+/// Interrupt vectors of the 8051 and 8052, after the reset vector at 0000H.
+pub const VECTORS: [(usize, &str); 6] = [
+    (0x03, "external 0"),
+    (0x0B, "timer 0"),
+    (0x13, "external 1"),
+    (0x1B, "timer 1"),
+    (0x23, "serial"),
+    (0x2B, "timer 2"),
+];
+
+/// Value of `SP` after reset.
+pub const RESET_SP: usize = 0x07;
+
+/// Stack depths beyond this only come from a `PUSH` in a loop.
+const DEPTH_LIMIT: i64 = 256;
+
+/// A routine entered by a call or an interrupt.
+pub struct Function {
+    pub name: String,
+    pub entry: usize,
+    /// Entry addresses of the routines it calls.
+    pub calls: Vec<usize>,
+    /// Worst-case stack bytes used from its entry on, `None` when unbounded by recursion or
+    /// by pushing in a loop.
+    pub depth: Option<usize>,
+}
+
+/// A root of the call graph: the reset vector or an interrupt vector.
+pub struct EntryPoint {
+    pub name: &'static str,
+    pub vector: usize,
+    /// Entry addresses of the routines it calls.
+    pub calls: Vec<usize>,
+    /// Worst-case stack bytes including the return address an interrupt pushes.
+    pub depth: Option<usize>,
+}
+
+pub struct StackReport {
+    pub entry_points: Vec<EntryPoint>,
+    /// Every routine reachable from an entry point, in address order.
+    pub functions: Vec<Function>,
+    /// The main program plus the two deepest interrupts, as one interrupt of each priority
+    /// level can nest on top of it.
+    pub worst_case: Option<usize>,
+    pub initial_sp: usize,
+    /// Bytes of internal RAM above the initial `SP`.
+    pub available: usize,
+}
+
+impl StackReport {
+    pub fn overflows(&self) -> bool {
+        self.worst_case.is_none_or(|depth| depth > self.available)
+    }
+
+    /// Name of the routine or entry point at `entry`.
+    pub fn name(&self, entry: usize) -> String {
+        let function = self.functions.iter().find(|function| function.entry == entry);
+        let point = self.entry_points.iter().find(|point| point.vector == entry);
+        match (function, point) {
+            (Some(function), _) => function.name.clone(),
+            (_, Some(point)) => point.name.to_string(),
+            _ => format!("{:04X}H", entry),
+        }
+    }
+}
+
+/// The value of the first `MOV SP, #value` in the code.
+pub fn initial_sp(rows: &[Row]) -> Option<usize> {
+    rows.iter().find_map(|row| match row.encoding.as_deref() {
+        Some([0x75, 0x81, value]) if !row.inactive => Some(*value as usize),
+        _ => None,
+    })
+}
+
+/// Parses the value given to `--sp`.
+pub fn parse_sp(text: &str) -> Result<usize, String> {
+    parse_number(text.trim())
+        .filter(|value| (0..=0xFF).contains(value))
+        .map(|value| value as usize)
+        .ok_or_else(|| format!("invalid stack pointer `{}`", text))
+}
+
+struct Analyzer<'a> {
+    cfg: &'a Cfg,
+    rows: &'a [Row],
+    /// Block index by start address.
+    starts: HashMap<usize, usize>,
+    /// Blocks reached from each block without following calls.
+    successors: Vec<Vec<usize>>,
+    depths: HashMap<usize, Option<usize>>,
+    calls: HashMap<usize, Vec<usize>>,
+    /// Routines whose depth is being computed, to detect recursion.
+    active: HashSet<usize>,
+}
+
+impl Analyzer<'_> {
+    fn depth(&mut self, entry: usize) -> Option<usize> {
+        if let Some(depth) = self.depths.get(&entry) {
+            return *depth;
+        }
+        if !self.active.insert(entry) {
+            return None;
+        }
+        let res = self.walk(entry);
+        self.active.remove(&entry);
+        self.depths.insert(entry, res);
+        res
+    }
+
+    fn walk(&mut self, entry: usize) -> Option<usize> {
+        let Some(&start) = self.starts.get(&entry) else {
+            return Some(0);
+        };
+        let mut entry_depths = HashMap::from([(start, 0)]);
+        let mut work = vec![start];
+        let mut calls = vec![];
+        let mut max = 0;
+        let mut bounded = true;
+        while let Some(block) = work.pop() {
+            let mut depth = entry_depths[&block];
+            for &index in &self.cfg.blocks[block].rows {
+                let row = &self.rows[index];
+                match row.mnemonic.as_deref() {
+                    Some("PUSH") => depth += 1,
+                    Some("POP") => depth -= 1,
+                    Some("ACALL" | "LCALL") => {
+                        let callee = match row.target {
+                            Some(target) => {
+                                calls.push(target);
+                                self.depth(target)
+                            }
+                            None => Some(0),
+                        };
+                        match callee {
+                            Some(callee) => max = max.max(depth + 2 + callee as i64),
+                            None => bounded = false,
+                        }
+                    }
+                    _ => {}
+                }
+                max = max.max(depth);
+            }
+            for &next in &self.successors[block] {
+                if entry_depths.get(&next).is_none_or(|&known| depth > known) {
+                    if depth > DEPTH_LIMIT {
+                        bounded = false;
+                        continue;
+                    }
+                    entry_depths.insert(next, depth);
+                    work.push(next);
+                }
+            }
+        }
+        calls.sort();
+        calls.dedup();
+        self.calls.insert(entry, calls);
+        bounded.then_some(max as usize)
+    }
+}
+
+impl StackReport {
+    /// Analyzes the stack use of every entry point, with `ram` bytes of internal RAM and
+    /// `SP` starting at `initial_sp`.
+    pub fn of(rows: &[Row], initial_sp: usize, ram: usize) -> Self {
+        let cfg = Cfg::of(rows);
+        let starts = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.start, index))
+            .collect::<HashMap<_, _>>();
+        let mut successors = vec![vec![]; cfg.blocks.len()];
+        for edge in cfg.edges.iter().filter(|edge| edge.kind != EdgeKind::Call) {
+            successors[edge.from].push(edge.to);
+        }
+        let mut analyzer = Analyzer {
+            cfg: &cfg,
+            rows,
+            starts,
+            successors,
+            depths: HashMap::new(),
+            calls: HashMap::new(),
+            active: HashSet::new(),
+        };
+
+        let reset = match analyzer.starts.contains_key(&0) {
+            true => 0,
+            false => cfg.blocks.first().map_or(0, |block| block.start),
+        };
+        let mut entry_points = vec![EntryPoint {
+            name: "reset",
+            vector: reset,
+            depth: analyzer.depth(reset),
+            calls: analyzer.calls.get(&reset).cloned().unwrap_or_default(),
+        }];
+        for (vector, name) in VECTORS {
+            if analyzer.starts.contains_key(&vector) {
+                let depth = analyzer.depth(vector).map(|depth| depth + 2);
+                let calls = analyzer.calls.get(&vector).cloned().unwrap_or_default();
+                entry_points.push(EntryPoint { name, vector, calls, depth });
+            }
+        }
+
+        let labels = cfg
+            .blocks
+            .iter()
+            .filter_map(|block| Some((block.start, block.label.clone()?)))
+            .collect::<HashMap<_, _>>();
+        let mut functions = analyzer
+            .calls
+            .iter()
+            .filter(|(entry, _)| !entry_points.iter().any(|point| point.vector == **entry))
+            .map(|(&entry, calls)| Function {
+                name: labels
+                    .get(&entry)
+                    .cloned()
+                    .unwrap_or_else(|| format!("{:04X}H", entry)),
+                entry,
+                calls: calls.clone(),
+                depth: analyzer.depths.get(&entry).copied().flatten(),
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|function| function.entry);
+
+        let mut interrupts = entry_points[1..]
+            .iter()
+            .map(|point| point.depth)
+            .collect::<Option<Vec<_>>>();
+        if let Some(interrupts) = &mut interrupts {
+            interrupts.sort();
+            interrupts.reverse();
+            interrupts.truncate(2);
+        }
+        let worst_case = entry_points[0]
+            .depth
+            .zip(interrupts)
+            .map(|(main, interrupts)| main + interrupts.iter().sum::<usize>());
+        StackReport {
+            entry_points,
+            functions,
+            worst_case,
+            initial_sp,
+            available: ram.saturating_sub(initial_sp + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn depths() {
+        let source = "ORG 0\nAJMP MAIN\nORG 0BH\nAJMP TICK\nORG 30H\nMAIN: MOV SP, #6FH\nLOOP: ACALL WORK\nSJMP LOOP\nWORK: PUSH ACC\nACALL LEAF\nPOP ACC\nRET\nLEAF: RET\nTICK: PUSH PSW\nPUSH ACC\nPOP ACC\nPOP PSW\nRETI";
+        let rows = analyze(source, &AnalysisOptions::default());
+        let sp = initial_sp(&rows).unwrap();
+        assert_eq!(0x6F, sp);
+        let report = StackReport::of(&rows, sp, 128);
+        let depths = report
+            .entry_points
+            .iter()
+            .map(|point| (point.name, point.vector, point.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("reset", 0, Some(5)), ("timer 0", 0x0B, Some(4))], depths);
+        let functions = report
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.calls.clone(), function.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("WORK", vec![0x3E], Some(3)), ("LEAF", vec![], Some(0))],
+            functions
+        );
+        assert_eq!(Some(9), report.worst_case);
+        assert_eq!(16, report.available);
+        assert!(!report.overflows());
+
+        let rows = analyze("MAIN: ACALL MAIN", &AnalysisOptions::default());
+        let report = StackReport::of(&rows, RESET_SP, 128);
+        assert_eq!(None, report.worst_case);
+        assert!(report.overflows());
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LabelCase {
+    Sensitive,
+    /// `loop`, `Loop` and `LOOP` name the same symbol, as in A51.
+    #[default]
+    Insensitive,
+}
+
+/// Values of user labels and `EQU` constants.
+#[derive(Clone, Default)]
+pub struct SymbolTable {
+    values: HashMap<String, i64>,
+    case: LabelCase,
+}
+
+impl SymbolTable {
+    pub fn new(case: LabelCase) -> Self {
+        SymbolTable {
+            values: HashMap::new(),
+            case,
+        }
+    }
+
+    fn key(&self, name: &str) -> String {
+        match self.case {
+            LabelCase::Sensitive => name.to_string(),
+            LabelCase::Insensitive => name.to_ascii_uppercase(),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, value: i64) {
+        let key = self.key(name);
+        self.values.insert(key, value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<i64> {
+        self.values.get(&self.key(name)).copied()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(&self.key(name))
+    }
+}
+
+impl<const N: usize> From<[(&str, i64); N]> for SymbolTable {
+    fn from(values: [(&str, i64); N]) -> Self {
+        let mut res = SymbolTable::default();
+        for (name, value) in values {
+            res.insert(name, value);
+        }
+        res
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{literal::intel_hex, report::cycle_time};
+
+/// What a timer is set up for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Goal {
+    /// A UART baud rate, with the timer clocking the serial port.
+    Baud(f64),
+    /// Seconds between overflows.
+    Period(f64),
+}
+
+/// Machine cycles a timer counts before it overflows in each mode.
+fn range(mode: usize) -> usize {
+    match mode {
+        0 => 1 << 13,
+        1 => 1 << 16,
+        _ => 1 << 8,
+    }
+}
+
+/// Parses the serial baud rate given to `--baud`.
+pub fn parse_baud(text: &str) -> Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(value),
+        _ => Err(format!("invalid baud rate `{}`", text)),
+    }
+}
+
+/// A timer's mode and reload value reaching a goal as closely as the clock allows.
+pub struct Setting {
+    pub timer: usize,
+    pub mode: usize,
+    /// Whether `SMOD` in `PCON` doubles the baud rate.
+    pub smod: bool,
+    pub goal: Goal,
+    /// Machine cycles between overflows.
+    pub ticks: usize,
+    /// The value the timer counts up from.
+    pub reload: usize,
+    /// The baud rate or period reached.
+    pub achieved: f64,
+}
+
+impl Setting {
+    pub fn of(clock: f64, timer: usize, mode: usize, smod: bool, goal: Goal) -> Result<Self, String> {
+        if mode == 3 && timer == 1 {
+            return Err(String::from("timer 1 stops in mode 3"));
+        }
+        let doubling = if smod { 2.0 } else { 1.0 };
+        let exact = match goal {
+            Goal::Baud(baud) => clock / 12.0 * doubling / 32.0 / baud,
+            Goal::Period(seconds) => seconds / cycle_time(1, clock),
+        };
+        let ticks = exact.round() as usize;
+        if ticks == 0 || ticks > range(mode) {
+            return Err(format!(
+                "{:.1} machine cycles between overflows, but mode {} counts 1 to {}",
+                exact,
+                mode,
+                range(mode)
+            ));
+        }
+        let achieved = match goal {
+            Goal::Baud(_) => clock / 12.0 * doubling / 32.0 / ticks as f64,
+            Goal::Period(_) => cycle_time(ticks, clock),
+        };
+        Ok(Setting {
+            timer,
+            mode,
+            smod,
+            goal,
+            ticks,
+            reload: range(mode) - ticks,
+            achieved,
+        })
+    }
+
+    /// Deviation of the achieved from the wanted baud rate or period, in percent.
+    pub fn error(&self) -> f64 {
+        let (Goal::Baud(target) | Goal::Period(target)) = self.goal;
+        (self.achieved - target) / target * 100.0
+    }
+
+    /// Value of `TMOD` running the timer from the oscillator in its mode.
+    pub fn tmod(&self) -> usize {
+        self.mode << (4 * self.timer)
+    }
+
+    /// The timer registers and their values, `TH` first.
+    pub fn registers(&self) -> Vec<(String, usize)> {
+        let register = |half| format!("{}{}", half, self.timer);
+        match self.mode {
+            0 => vec![(register("TH"), self.reload >> 5), (register("TL"), self.reload & 0x1F)],
+            1 => vec![(register("TH"), self.reload >> 8), (register("TL"), self.reload & 0xFF)],
+            2 => vec![(register("TH"), self.reload), (register("TL"), self.reload)],
+            _ => vec![(register("TL"), self.reload)],
+        }
+    }
+
+    /// Instructions setting the timer up and starting it, and for a baud rate the serial
+    /// port in mode 1.
+    pub fn init_code(&self) -> String {
+        let mut lines = vec![format!("MOV TMOD, #{}", intel_hex(self.tmod(), 2))];
+        for (register, value) in self.registers() {
+            lines.push(format!("MOV {}, #{}", register, intel_hex(value, 2)));
+        }
+        if let Goal::Baud(_) = self.goal {
+            if self.smod {
+                lines.push(String::from("ORL PCON, #80H"));
+            }
+            lines.push(String::from("MOV SCON, #50H"));
+        }
+        lines.push(format!("SETB TR{}", self.timer));
+        lines.into_iter().map(|line| format!("    {}\n", line)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn reloads() {
+        let setting = Setting::of(11.0592e6, 1, 2, false, Goal::Baud(9600.0)).unwrap();
+        assert_eq!((3, 0xFD), (setting.ticks, setting.reload));
+        assert!(setting.error().abs() < 1e-9);
+        assert_eq!(0x20, setting.tmod());
+
+        let setting = Setting::of(12e6, 1, 2, true, Goal::Baud(9600.0)).unwrap();
+        assert_eq!(0xF9, setting.reload);
+        assert!((setting.error() + 6.99).abs() < 0.01);
+
+        let setting = Setting::of(12e6, 0, 1, false, Goal::Period(0.01)).unwrap();
+        assert_eq!(0xD8F0, setting.reload);
+        let registers = vec![(String::from("TH0"), 0xD8), (String::from("TL0"), 0xF0)];
+        assert_eq!(registers, setting.registers());
+        let code = setting.init_code();
+        assert_eq!("    MOV TMOD, #01H\n    MOV TH0, #0D8H\n    MOV TL0, #0F0H\n    SETB TR0\n", code);
+        assert!(analyze(&code, &AnalysisOptions::default()).iter().all(|row| !row.has_error()));
+
+        let setting = Setting::of(12e6, 0, 0, false, Goal::Period(0.001)).unwrap();
+        assert_eq!(vec![(String::from("TH0"), 0xE0), (String::from("TL0"), 0x18)], setting.registers());
+        assert!(Setting::of(12e6, 0, 2, false, Goal::Period(0.001)).is_err());
+        assert!(Setting::of(12e6, 1, 3, false, Goal::Period(0.0001)).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+    cfg::{flow, Cfg, EdgeKind, Flow},
+    report::Row,
+    stack::VECTORS,
+};
+
+/// Worst-case execution time of a routine, from its first instruction to its return.
+pub struct Wcet {
+    pub name: String,
+    pub entry: usize,
+    /// Cycles, or why no bound could be found.
+    pub cycles: Result<usize, String>,
+}
+
+/// Parses a `--loop-bound` argument, `LABEL=N` for a loop starting at `LABEL` that runs at
+/// most `N` times.
+pub fn parse_loop_bound(text: &str) -> Result<(String, usize), String> {
+    let (label, bound) = text
+        .split_once('=')
+        .ok_or_else(|| String::from("expected LABEL=N"))?;
+    let bound = bound
+        .trim()
+        .parse()
+        .ok()
+        .filter(|bound| *bound > 0)
+        .ok_or_else(|| format!("invalid loop bound `{}`", bound))?;
+    Ok((label.trim().to_string(), bound))
+}
+
+/// The bound of a `@bound N` annotation in a comment.
+fn annotated_bound(comment: &str) -> Option<usize> {
+    let (_, rest) = comment.split_once("@bound")?;
+    rest.split_whitespace().next()?.parse().ok().filter(|bound| *bound > 0)
+}
+
+struct Analyzer<'a> {
+    cfg: Cfg,
+    rows: &'a [Row],
+    bounds: &'a [(String, usize)],
+    starts: HashMap<usize, usize>,
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    results: HashMap<usize, Result<usize, String>>,
+    /// Routines being analyzed, to detect recursion.
+    active: HashSet<usize>,
+}
+
+/// Follows collapsed loops to the header that now stands for `node`.
+fn find(rep: &HashMap<usize, usize>, mut node: usize) -> usize {
+    while let Some(&next) = rep.get(&node) {
+        node = next;
+    }
+    node
+}
+
+/// Longest path costs from `from` over the acyclic graph `succ`, each node adding its own cost.
+fn longest_paths(
+    from: usize,
+    succ: &HashMap<usize, BTreeSet<usize>>,
+    cost: &HashMap<usize, usize>,
+) -> Result<HashMap<usize, usize>, usize> {
+    // Depth-first post order gives a reverse topological order and finds cycles.
+    let mut order = vec![];
+    let mut state = HashMap::new();
+    let mut stack = vec![(from, false)];
+    while let Some((node, done)) = stack.pop() {
+        if done {
+            state.insert(node, true);
+            order.push(node);
+            continue;
+        }
+        match state.get(&node) {
+            Some(true) => continue,
+            Some(false) => return Err(node),
+            None => {}
+        }
+        state.insert(node, false);
+        stack.push((node, true));
+        for &next in succ.get(&node).into_iter().flatten() {
+            match state.get(&next) {
+                Some(false) => return Err(next),
+                Some(true) => {}
+                None => stack.push((next, false)),
+            }
+        }
+    }
+    let mut dist = HashMap::from([(from, cost[&from])]);
+    for &node in order.iter().rev() {
+        let Some(&base) = dist.get(&node) else {
+            continue;
+        };
+        for &next in succ.get(&node).into_iter().flatten() {
+            let total = base + cost[&next];
+            if dist.get(&next).is_none_or(|&known| total > known) {
+                dist.insert(next, total);
+            }
+        }
+    }
+    Ok(dist)
+}
+
+impl Analyzer<'_> {
+    fn address(&self, block: usize) -> usize {
+        self.cfg.blocks[block].start
+    }
+
+    fn routine(&mut self, entry: usize) -> Result<usize, String> {
+        if let Some(res) = self.results.get(&entry) {
+            return res.clone();
+        }
+        if !self.active.insert(entry) {
+            return Err(format!("the routine at {:04X}H is recursive", entry));
+        }
+        let res = self.analyze(entry);
+        self.active.remove(&entry);
+        self.results.insert(entry, res.clone());
+        res
+    }
+
+    /// The bound of the loop headed by `header` and closed by the branches at the end of
+    /// `latches`: from `--loop-bound`, a `@bound` comment or a `DJNZ` counter.
+    fn bound(&self, header: usize, latches: &[usize], outside: &[usize]) -> Option<usize> {
+        let block = &self.cfg.blocks[header];
+        if let Some(label) = &block.label {
+            let given = self.bounds.iter().find(|(name, _)| name.eq_ignore_ascii_case(label));
+            if let Some((_, bound)) = given {
+                return Some(*bound);
+            }
+        }
+        let annotated = [header]
+            .iter()
+            .chain(latches)
+            .flat_map(|&block| &self.cfg.blocks[block].rows)
+            .filter_map(|&index| self.rows[index].comment.as_deref())
+            .find_map(annotated_bound);
+        if annotated.is_some() {
+            return annotated;
+        }
+        latches.iter().find_map(|&latch| {
+            let branch = &self.rows[*self.cfg.blocks[latch].rows.last()?];
+            if branch.mnemonic.as_deref() != Some("DJNZ") {
+                return None;
+            }
+            let counter = branch.operands.first()?;
+            outside.iter().find_map(|&before| {
+                let row = self.cfg.blocks[before]
+                    .rows
+                    .iter()
+                    .rev()
+                    .map(|&index| &self.rows[index])
+                    .find(|row| row.operands.first().is_some_and(|op| op.eq_ignore_ascii_case(counter)))?;
+                let immediate = row.operands.get(1).is_some_and(|op| op.starts_with('#'));
+                if row.mnemonic.as_deref() != Some("MOV") || !immediate {
+                    return None;
+                }
+                // The counter is decremented before the test, so 0 runs 256 times.
+                match *row.encoding.as_ref()?.last()? {
+                    0 => Some(256),
+                    count => Some(count as usize),
+                }
+            })
+        })
+    }
+
+    fn analyze(&mut self, entry: usize) -> Result<usize, String> {
+        let Some(&start) = self.starts.get(&entry) else {
+            return Ok(0);
+        };
+        let mut reachable = vec![start];
+        let mut seen = HashSet::from([start]);
+        let mut index = 0;
+        while index < reachable.len() {
+            for &next in &self.successors[reachable[index]] {
+                if seen.insert(next) {
+                    reachable.push(next);
+                }
+            }
+            index += 1;
+        }
+
+        let mut cost = HashMap::new();
+        let mut succ = HashMap::new();
+        for &block in &reachable {
+            let mut cycles = self.cfg.blocks[block].cycles;
+            let rows = self.cfg.blocks[block].rows.clone();
+            for index in rows {
+                let row = &self.rows[index];
+                match row.mnemonic.as_deref().map(flow) {
+                    Some(Flow::Call) => {
+                        if let Some(target) = row.target {
+                            cycles += self.routine(target)?;
+                        }
+                    }
+                    Some(Flow::Indirect) => {
+                        return Err(format!("indirect jump at {:04X}H", row.address));
+                    }
+                    _ => {}
+                }
+            }
+            cost.insert(block, cycles);
+            succ.insert(block, self.successors[block].iter().copied().collect::<BTreeSet<_>>());
+        }
+
+        // Back edges close loops, found as edges to a block on the depth-first path.
+        let mut latches: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut on_path = HashSet::new();
+        let mut done = HashSet::new();
+        let mut stack = vec![(start, 0)];
+        on_path.insert(start);
+        while let Some((node, next)) = stack.pop() {
+            match self.successors[node].get(next) {
+                Some(&to) => {
+                    stack.push((node, next + 1));
+                    if on_path.contains(&to) {
+                        latches.entry(to).or_default().push(node);
+                    } else if !done.contains(&to) {
+                        on_path.insert(to);
+                        stack.push((to, 0));
+                    }
+                }
+                None => {
+                    on_path.remove(&node);
+                    done.insert(node);
+                }
+            }
+        }
+        let mut loops = latches
+            .into_iter()
+            .map(|(header, latches)| {
+                let mut body = HashSet::from([header]);
+                let mut work = latches.clone();
+                while let Some(node) = work.pop() {
+                    if body.insert(node) {
+                        work.extend(self.predecessors[node].iter().filter(|pred| seen.contains(pred)));
+                    }
+                }
+                (header, latches, body)
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|(header, _, body)| (body.len(), *header));
+
+        let mut rep = HashMap::new();
+        for (header, latches, body) in loops {
+            let at = self.address(header);
+            let body = body.iter().map(|&node| find(&rep, node)).collect::<HashSet<_>>();
+            let mut inner = HashMap::new();
+            let mut exits = BTreeSet::new();
+            let mut exiting = vec![];
+            for &node in &body {
+                let mut internal = BTreeSet::new();
+                for &next in &succ[&node] {
+                    let next = find(&rep, next);
+                    if !body.contains(&next) {
+                        exits.insert(next);
+                        exiting.push(node);
+                    } else if next != header {
+                        internal.insert(next);
+                    }
+                }
+                inner.insert(node, internal);
+            }
+            if exiting.is_empty() {
+                return Err(format!("the loop at {:04X}H never exits", at));
+            }
+            let outside = self.predecessors[header]
+                .iter()
+                .filter(|pred| seen.contains(pred) && !body.contains(&find(&rep, **pred)))
+                .copied()
+                .collect::<Vec<_>>();
+            let bound = self
+                .bound(header, &latches, &outside)
+                .ok_or_else(|| format!("the loop at {:04X}H has no bound", at))?;
+            let dist = longest_paths(header, &inner, &cost)
+                .map_err(|_| format!("the loop at {:04X}H is not structured", at))?;
+            let through = latches
+                .iter()
+                .filter_map(|&latch| dist.get(&find(&rep, latch)))
+                .max()
+                .copied()
+                .unwrap_or(0);
+            let out = exiting.iter().filter_map(|node| dist.get(node)).max().copied().unwrap_or(0);
+            for &node in body.iter().filter(|&&node| node != header) {
+                rep.insert(node, header);
+                cost.remove(&node);
+                succ.remove(&node);
+            }
+            cost.insert(header, (bound - 1) * through + out);
+            succ.insert(header, exits);
+        }
+
+        let succ = succ
+            .into_iter()
+            .map(|(node, next)| (node, next.into_iter().map(|next| find(&rep, next)).collect()))
+            .collect::<HashMap<_, BTreeSet<_>>>();
+        let dist = longest_paths(start, &succ, &cost)
+            .map_err(|node| format!("the loop at {:04X}H is not structured", self.address(node)))?;
+        Ok(dist
+            .iter()
+            .filter(|(node, _)| succ[node].is_empty())
+            .map(|(_, &cycles)| cycles)
+            .max()
+            .unwrap_or(0))
+    }
+}
+
+/// Bounds the execution time of every routine that is called or sits at an interrupt
+/// vector. Loops need a bound from `bounds`, a `@bound N` comment on their first or last
+/// line, or a `DJNZ` counter loaded with `MOV` just before the loop.
+pub fn wcet(rows: &[Row], bounds: &[(String, usize)]) -> Vec<Wcet> {
+    let cfg = Cfg::of(rows);
+    let starts = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(index, block)| (block.start, index))
+        .collect::<HashMap<_, _>>();
+    let mut successors = vec![vec![]; cfg.blocks.len()];
+    let mut predecessors = vec![vec![]; cfg.blocks.len()];
+    for edge in cfg.edges.iter().filter(|edge| edge.kind != EdgeKind::Call) {
+        successors[edge.from].push(edge.to);
+        predecessors[edge.to].push(edge.from);
+    }
+
+    let mut entries = VECTORS
+        .iter()
+        .filter(|(vector, _)| starts.contains_key(vector))
+        .map(|(vector, name)| (*vector, name.to_string()))
+        .collect::<Vec<_>>();
+    let mut targets = cfg
+        .edges
+        .iter()
+        .filter(|edge| edge.kind == EdgeKind::Call)
+        .map(|edge| edge.to)
+        .collect::<Vec<_>>();
+    targets.sort();
+    targets.dedup();
+    for target in targets {
+        let block = &cfg.blocks[target];
+        let name = block
+            .label
+            .clone()
+            .unwrap_or_else(|| format!("{:04X}H", block.start));
+        entries.push((block.start, name));
+    }
+
+    let mut analyzer = Analyzer {
+        cfg,
+        rows,
+        bounds,
+        starts,
+        successors,
+        predecessors,
+        results: HashMap::new(),
+        active: HashSet::new(),
+    };
+    entries
+        .into_iter()
+        .map(|(entry, name)| Wcet {
+            name,
+            entry,
+            cycles: analyzer.routine(entry),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{analyze, AnalysisOptions};
+
+    #[test]
+    fn bounds() {
+        let source = "ORG 0BH\nAJMP TICK\nORG 30H\nMAIN: ACALL DELAY\nACALL WAIT\nSJMP MAIN\nDELAY: MOV R7, #10\nOUTER: MOV R6, #0\nINNER: DJNZ R6, INNER\nDJNZ R7, OUTER\nRET\nWAIT: JNB P1.0, WAIT ; @bound 5\nRET\nTICK: JB F0, SKIP\nCPL P1.1\nSKIP: RETI";
+        let rows = analyze(source, &AnalysisOptions::default());
+        let costs = |bounds: &[(String, usize)]| {
+            wcet(&rows, bounds)
+                .into_iter()
+                .map(|routine| (routine.name, routine.cycles))
+                .collect::<Vec<_>>()
+        };
+        // DELAY: 1 + 10 * (1 + 256 * 2 + 2) + 2, WAIT: 5 * 2 + 2, timer 0: 2 + 2 + 1 + 2.
+        assert_eq!(
+            vec![
+                (String::from("timer 0"), Ok(7)),
+                (String::from("DELAY"), Ok(5153)),
+                (String::from("WAIT"), Ok(12)),
+            ],
+            costs(&[])
+        );
+        assert_eq!(Ok((String::from("OUTER"), 3)), parse_loop_bound("OUTER=3"));
+        assert_eq!(Ok(1 + 3 * 515 + 2), costs(&[parse_loop_bound("OUTER=3").unwrap()])[1].1);
+    }
+}